@@ -27,6 +27,17 @@ impl Error for ServiceError {}
 pub struct FetchError {
     pub message: String,
     pub url: String,
+    pub kind: FetchErrorKind,
+}
+
+/// The kind of failure behind a `FetchError`, for callers which act differently depending on it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FetchErrorKind {
+    /// The request couldn't connect to the service at all, so another provider may succeed.
+    ConnectionFailed,
+    /// Any other failure.
+    #[default]
+    Other,
 }
 
 impl Display for FetchError {
@@ -39,8 +50,114 @@ impl Display for FetchError {
     }
 }
 
+impl FetchError {
+    /// Create an error for a request which couldn't connect to the service at all.
+    pub fn connection_failed(url: String) -> Self {
+        FetchError {
+            message: String::from("Couldn't connect to the service."),
+            url,
+            kind: FetchErrorKind::ConnectionFailed,
+        }
+    }
+
+    /// Check whether the request couldn't connect to the service at all, in which case another
+    /// provider of the service may succeed.
+    pub fn is_connection_failure(&self) -> bool {
+        self.kind == FetchErrorKind::ConnectionFailed
+    }
+}
+
 impl Error for FetchError {}
 
+/// Error returned by Auth service requests that distinguishes the common failure statuses.
+#[derive(Debug)]
+pub enum AuthError {
+    /// The service credentials don't grant permission for the request (403).
+    Forbidden(FetchError),
+    /// The principal, permission or target doesn't exist (404).
+    NotFound(FetchError),
+    /// The request conflicts with the current state of the Auth service (409).
+    Conflict(FetchError),
+    /// Any other failure, including failure to make the request at all.
+    Fetch(FetchError),
+}
+
+impl AuthError {
+    /// Build the error corresponding to an unsuccessful response status.
+    pub fn from_status(status: http::StatusCode, fetch_error: FetchError) -> Self {
+        match status {
+            http::StatusCode::FORBIDDEN => AuthError::Forbidden(fetch_error),
+            http::StatusCode::NOT_FOUND => AuthError::NotFound(fetch_error),
+            http::StatusCode::CONFLICT => AuthError::Conflict(fetch_error),
+            _ => AuthError::Fetch(fetch_error),
+        }
+    }
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::Forbidden(e) => write!(f, "Auth error (forbidden): {}", e),
+            AuthError::NotFound(e) => write!(f, "Auth error (not found): {}", e),
+            AuthError::Conflict(e) => write!(f, "Auth error (conflict): {}", e),
+            AuthError::Fetch(e) => write!(f, "Auth error: {}", e),
+        }
+    }
+}
+
+impl From<FetchError> for AuthError {
+    fn from(fetch_error: FetchError) -> Self {
+        AuthError::Fetch(fetch_error)
+    }
+}
+
+impl Error for AuthError {}
+
+/// Error returned by conditional ConfigDB writes that distinguishes a failed precondition.
+#[derive(Debug)]
+pub enum ConfigDbError {
+    /// The entry has changed since its ETag was read, so the write wasn't made (412).
+    PreconditionFailed(FetchError),
+    /// The ConfigDB didn't return an ETag for an existing entry, so it can't be written
+    /// conditionally.
+    MissingEtag(FetchError),
+    /// Any other failure, including failure to make the request at all.
+    Fetch(FetchError),
+}
+
+impl Display for ConfigDbError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigDbError::PreconditionFailed(e) => {
+                write!(f, "ConfigDB error (precondition failed): {}", e)
+            }
+            ConfigDbError::MissingEtag(e) => write!(f, "ConfigDB error (missing ETag): {}", e),
+            ConfigDbError::Fetch(e) => write!(f, "ConfigDB error: {}", e),
+        }
+    }
+}
+
+impl From<FetchError> for ConfigDbError {
+    fn from(fetch_error: FetchError) -> Self {
+        ConfigDbError::Fetch(fetch_error)
+    }
+}
+
+impl Error for ConfigDbError {}
+
+#[derive(Debug)]
+pub struct BuilderError {
+    pub message: String,
+}
+
+impl Display for BuilderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Couldn't build service client: {}", self.message)
+    }
+}
+
+impl Error for BuilderError {}
+
 #[derive(Debug)]
 pub struct MqttError {
     pub message: String,
@@ -7,48 +7,66 @@ use std::fmt::{Display, Formatter};
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::Duration;
 
-use tokio::sync::Mutex;
+use futures::future::Shared;
 
-use crate::error::FetchError;
+use crate::error::{BuilderError, FetchError};
 use crate::service::auth::AuthInterface;
+use crate::service::clusters::ClustersInterface;
 use crate::service::cmdesc::CmdEscInterface;
 use crate::service::configdb::ConfigDbInterface;
 use crate::service::directory::DirectoryInterface;
+use crate::service::discovery::ProviderSelection;
+use crate::service::git::GitInterface;
 use crate::service::mqtt::MQTTInterface;
 use crate::service::request::{FetchOpts, HttpRequestMethod};
 use crate::service::response::{FetchResponse, PingResponse, TokenStruct};
+use crate::service::service_http::ServiceHttp;
+use crate::service::token::TokenKey;
 use crate::uuids;
 
 pub mod auth;
+pub mod clusters;
 mod cmdesc;
 pub mod configdb;
 pub mod directory;
 pub mod discovery;
+pub mod git;
 pub mod mqtt;
+pub mod service_http;
+pub mod token;
 
 /// Complex type to hold tokens in flight.
-pub type InFlightTokensMap =
-    HashMap<String, Pin<Box<dyn Future<Output = Result<TokenStruct, FetchError>> + Send>>>;
+///
+/// Each pending token request is shared so that every caller waiting on a token from the same
+/// service provider awaits the same request.
+pub type InFlightTokensMap = HashMap<
+    TokenKey,
+    Shared<Pin<Box<dyn Future<Output = Result<TokenStruct, FetchError>> + Send>>>,
+>;
 
 /// Struct to hold the Factory+ service interfaces and service urls.
 pub struct ServiceClient {
-    tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
-    http_client: Arc<reqwest::Client>,
+    service_http: Arc<ServiceHttp>,
 
     pub auth_interface: AuthInterface,
     pub config_db_interface: ConfigDbInterface,
     pub directory_interface: DirectoryInterface,
     pub mqtt_interface: MQTTInterface,
     pub cmd_esc_interface: CmdEscInterface,
+    pub git_interface: GitInterface,
+    pub clusters_interface: ClustersInterface,
 
-    service_creds: ServiceCreds,
     pub root_principle: Option<String>,
     pub permission_group: Option<String>,
 }
 
 impl ServiceClient {
     /// Create a new `ServiceClient` from the given credentials and urls.
+    ///
+    /// Service urls are looked up in the Directory when they are first needed. Use
+    /// `ServiceClient::builder` to configure service urls explicitly or to supply an HTTP client.
     pub async fn from(
         service_username: &str,
         service_password: &str,
@@ -56,81 +74,46 @@ impl ServiceClient {
         permission_group: Option<&str>,
         directory_url: &str,
     ) -> Self {
-        let client = Arc::new(reqwest::Client::new());
-        let tokens = Arc::new(Mutex::new(HashMap::new()));
-
-        let directory_interface = DirectoryInterface::from(
-            String::from(service_username),
-            String::from(service_password),
-            Arc::clone(&client),
-            String::from(directory_url),
-            Arc::clone(&tokens),
-        );
-
-        let configdb_urls = directory_interface
-            .service_urls(ServiceType::ConfigDb)
-            .await
-            .unwrap();
-        let mqtt_urls = directory_interface
-            .service_urls(ServiceType::MQTT)
-            .await
-            .unwrap();
-        let auth_urls = directory_interface
-            .service_urls(ServiceType::Authentication)
-            .await
-            .unwrap();
-        let cmd_esc_urls = directory_interface
-            .service_urls(ServiceType::CommandEscalation)
-            .await
-            .unwrap();
-
-        let config_db_interface = ConfigDbInterface::from(
-            String::from(service_username),
-            String::from(service_password),
-            Arc::clone(&client),
-            String::from(directory_url),
-            configdb_urls.unwrap().first().unwrap().clone(),
-            Arc::clone(&tokens),
-        );
-
-        let mqtt_interface = MQTTInterface::from(
-            String::from(service_username),
-            String::from(service_password),
-            Arc::clone(&client),
-            mqtt_urls.unwrap().first().unwrap().clone(),
-            Arc::clone(&tokens),
+        let service_http = Arc::new(ServiceHttp::from(
+            service_username,
+            service_password,
+            Arc::new(reqwest::Client::new()),
+        ));
+        service_http.set_service_url(
+            ServiceType::Directory,
+            String::from(directory_url.trim_end_matches('/')),
         );
 
-        let auth_interface = AuthInterface::from(
-            String::from(service_username),
-            String::from(service_password),
-            Arc::clone(&client),
-            String::from(directory_url),
-            auth_urls.unwrap().first().unwrap().clone(),
-            Arc::clone(&tokens),
-        );
+        ServiceClient::from_service_http(
+            service_http,
+            root_principle.map(String::from),
+            permission_group.map(String::from),
+        )
+    }
 
-        let cmd_esc_interface = CmdEscInterface::from(
-            String::from(service_username),
-            String::from(service_password),
-            Arc::clone(&client),
-            cmd_esc_urls.unwrap().first().unwrap().clone(),
-            Arc::clone(&tokens),
-        );
+    /// Start building a `ServiceClient`.
+    pub fn builder() -> ServiceClientBuilder {
+        ServiceClientBuilder::new()
+    }
 
+    fn from_service_http(
+        service_http: Arc<ServiceHttp>,
+        root_principle: Option<String>,
+        permission_group: Option<String>,
+    ) -> Self {
         ServiceClient {
-            tokens,
-            http_client: Arc::clone(&client),
-
-            service_creds: ServiceCreds::from(service_username, service_password),
-            root_principle: root_principle.map(String::from),
-            permission_group: permission_group.map(String::from),
-
-            auth_interface,
-            config_db_interface,
-            directory_interface,
-            mqtt_interface,
-            cmd_esc_interface,
+            auth_interface: AuthInterface::from(Arc::clone(&service_http)),
+            config_db_interface: ConfigDbInterface::from(Arc::clone(&service_http)),
+            directory_interface: DirectoryInterface::from(Arc::clone(&service_http)),
+            mqtt_interface: MQTTInterface::from(Arc::clone(&service_http)),
+            cmd_esc_interface: CmdEscInterface::from(Arc::clone(&service_http)),
+            git_interface: GitInterface::from(Arc::clone(&service_http)),
+            clusters_interface: ClustersInterface::from(Arc::clone(&service_http)),
+
+            service_http,
+
+            root_principle,
+            permission_group,
         }
     }
 
@@ -140,18 +123,8 @@ impl ServiceClient {
     /// As a side effect, this function gets a new token for authentication against the given
     /// service.
     pub async fn ping(&self, service: ServiceType) -> Result<PingResponse, FetchError> {
-        let service_url = match service {
-            ServiceType::Directory => self.directory_interface.service_url.clone(),
-            ServiceType::ConfigDb => self.config_db_interface.service_url.clone(),
-            ServiceType::Authentication => self.auth_interface.service_url.clone(),
-            ServiceType::MQTT => self.mqtt_interface.service_url.clone(),
-            ServiceType::CommandEscalation => self.cmd_esc_interface.service_url.clone(),
-        };
-
-        let ping_url = format!("{}/ping", service_url);
-
         let fetch_opts = FetchOpts {
-            url: ping_url.clone(),
+            url: String::from("/ping"),
             service,
             method: HttpRequestMethod::GET,
             headers: Default::default(),
@@ -165,124 +138,210 @@ impl ServiceClient {
         }
     }
 
+    /// Make an authenticated request to a service.
+    ///
+    /// If the held token for the service is rejected with a 401, a new token is requested and the
+    /// request is retried once.
     pub async fn fetch(&self, fetch_opts: FetchOpts) -> Result<FetchResponse, FetchError> {
-        let current_service_token = self
-            .get_service_token(
-                Arc::clone(&self.http_client),
-                fetch_opts.service,
-                &self.service_creds.service_username,
-                &self.service_creds.service_password,
-                &self.tokens,
-            )
-            .await?;
-
-        let headers =
-            utils::check_correct_headers(&fetch_opts.headers, &fetch_opts.body, &fetch_opts.url)?;
-
-        if let Ok(request) = match (fetch_opts.query, fetch_opts.body) {
-            (None, None) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers),
-            (Some(query), None) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers)
-                .query(&query),
-            (None, Some(body)) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers)
-                .body(body),
-            (Some(query), Some(body)) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers)
-                .query(&query)
-                .body(body),
-        }
-        .bearer_auth(current_service_token.token)
-        .build()
-        {
-            match self.http_client.execute(request).await {
-                Ok(response) => {
-                    let response_status = response.status();
-
-                    if let Ok(response_body) = response.text().await {
-                        Ok(FetchResponse {
-                            status: response_status,
-                            content: response_body,
-                        })
-                    } else {
-                        Err(FetchError {
-                            message: String::from("Couldn't decode response body."),
-                            url: fetch_opts.url,
-                        })
-                    }
-                }
-                _ => Err(FetchError {
-                    message: String::from("Couldn't make request."),
-                    url: fetch_opts.url,
-                }),
-            }
-        } else {
-            Err(FetchError {
-                message: String::from("Couldn't build a request to fetch."),
-                url: fetch_opts.url,
-            })
-        }
+        self.service_http.fetch(fetch_opts).await
     }
 
     pub async fn re_auth_service(&self, service: ServiceType) -> Result<TokenStruct, FetchError> {
-        let service_url = match service {
-            ServiceType::Directory => self.directory_interface.service_url.clone(),
-            ServiceType::ConfigDb => self.config_db_interface.service_url.clone(),
-            ServiceType::Authentication => self.auth_interface.service_url.clone(),
-            ServiceType::MQTT => self.mqtt_interface.service_url.clone(),
-            ServiceType::CommandEscalation => self.cmd_esc_interface.service_url.clone(),
-        };
+        self.service_http.refresh_token(service).await
+    }
 
-        let new_token = fetch_util::get_new_token(
-            Arc::clone(&self.http_client),
-            service_url,
-            &self.service_creds.service_username,
-            &self.service_creds.service_password,
-        )
-        .await?;
+    /// Set the url of a service, bypassing the Directory for that service.
+    pub fn set_service_url(&self, service: ServiceType, service_url: &str) {
+        self.service_http
+            .set_service_url(service, String::from(service_url.trim_end_matches('/')));
+    }
 
-        self.tokens.lock().await.insert(service, new_token.clone());
+    /// Add a url for a service alongside any already set, bypassing the Directory for that
+    /// service.
+    pub fn add_service_url(&self, service: ServiceType, service_url: &str) {
+        self.service_http
+            .add_service_url(service, String::from(service_url.trim_end_matches('/')));
+    }
 
-        Ok(new_token)
+    /// Set how to choose between the providers of a service when more than one is known.
+    pub fn set_provider_selection(&self, service: ServiceType, selection: ProviderSelection) {
+        self.service_http.set_provider_selection(service, selection);
     }
-    async fn get_service_token(
-        &self,
-        client: Arc<reqwest::Client>,
+
+    /// Set how long before a token's expiry it is refreshed. This applies to every interface.
+    pub fn set_token_refresh_skew(&self, skew: Duration) {
+        self.service_http.tokens().set_refresh_skew(skew);
+    }
+
+    /// Get the authenticated HTTP layer shared by the service interfaces.
+    pub fn service_http(&self) -> Arc<ServiceHttp> {
+        Arc::clone(&self.service_http)
+    }
+
+    pub async fn show_tokens(&self) {
+        println!("{:?}", self.service_http.tokens().snapshot().await);
+    }
+}
+
+/// The services a `ServiceClient` has an interface for, other than the Directory.
+const CLIENT_SERVICES: [ServiceType; 6] = [
+    ServiceType::Authentication,
+    ServiceType::ConfigDb,
+    ServiceType::MQTT,
+    ServiceType::CommandEscalation,
+    ServiceType::Git,
+    ServiceType::Clusters,
+];
+
+/// Builder for a `ServiceClient`.
+///
+/// Services given an explicit url are never looked up in the Directory. All other services are
+/// looked up in the Directory when they are first needed, so building doesn't make any requests.
+pub struct ServiceClientBuilder {
+    service_creds: Option<ServiceCreds>,
+    service_urls: HashMap<ServiceType, Vec<String>>,
+    http_client: Option<reqwest::Client>,
+    token_refresh_skew: Option<Duration>,
+    discovery_ttl: Option<Duration>,
+    provider_selection: HashMap<ServiceType, ProviderSelection>,
+    root_principle: Option<String>,
+    permission_group: Option<String>,
+}
+
+impl ServiceClientBuilder {
+    pub fn new() -> Self {
+        ServiceClientBuilder {
+            service_creds: None,
+            service_urls: HashMap::new(),
+            http_client: None,
+            token_refresh_skew: None,
+            discovery_ttl: None,
+            provider_selection: HashMap::new(),
+            root_principle: None,
+            permission_group: None,
+        }
+    }
+
+    /// Set the credentials used to authenticate against every service.
+    pub fn credentials(mut self, service_username: &str, service_password: &str) -> Self {
+        self.service_creds = Some(ServiceCreds::from(service_username, service_password));
+        self
+    }
+
+    /// Set the url of the Directory service.
+    pub fn directory_url(self, directory_url: &str) -> Self {
+        self.service_url(ServiceType::Directory, directory_url)
+    }
+
+    /// Set the url of a service, bypassing the Directory for that service.
+    pub fn service_url(mut self, service: ServiceType, service_url: &str) -> Self {
+        self.service_urls.insert(
+            service,
+            vec![String::from(service_url.trim_end_matches('/'))],
+        );
+        self
+    }
+
+    /// Add a url for a service alongside any already set, bypassing the Directory for that
+    /// service.
+    pub fn add_service_url(mut self, service: ServiceType, service_url: &str) -> Self {
+        self.service_urls
+            .entry(service)
+            .or_default()
+            .push(String::from(service_url.trim_end_matches('/')));
+        self
+    }
+
+    /// Set how to choose between the providers of a service when more than one is known.
+    pub fn provider_selection(
+        mut self,
         service: ServiceType,
-        username: &String,
-        password: &String,
-        tokens: &Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
-    ) -> Result<TokenStruct, FetchError> {
-        let mut locked_tokens = tokens.lock().await;
-        // If we find a local token, return it. Otherwise, we request a new one.
-        if let Some(token) = locked_tokens.get(&service) {
-            Ok(token.clone())
-        } else {
-            let service_url = match service {
-                ServiceType::Directory => self.directory_interface.service_url.clone(),
-                ServiceType::ConfigDb => self.config_db_interface.service_url.clone(),
-                ServiceType::Authentication => self.auth_interface.service_url.clone(),
-                ServiceType::MQTT => self.mqtt_interface.service_url.clone(),
-                ServiceType::CommandEscalation => self.cmd_esc_interface.service_url.clone(),
-            };
-            let new_token =
-                fetch_util::get_new_token(client, service_url.clone(), username, password).await?;
-            locked_tokens.insert(service, new_token.clone());
-            Ok(new_token)
+        selection: ProviderSelection,
+    ) -> Self {
+        self.provider_selection.insert(service, selection);
+        self
+    }
+
+    /// Use the given HTTP client for every request rather than a default one.
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = Some(http_client);
+        self
+    }
+
+    /// Set how long before a token's expiry it is refreshed.
+    pub fn token_refresh_skew(mut self, skew: Duration) -> Self {
+        self.token_refresh_skew = Some(skew);
+        self
+    }
+
+    /// Set how long service urls found in the Directory are cached for.
+    pub fn discovery_ttl(mut self, ttl: Duration) -> Self {
+        self.discovery_ttl = Some(ttl);
+        self
+    }
+
+    pub fn root_principle(mut self, root_principle: &str) -> Self {
+        self.root_principle = Some(String::from(root_principle));
+        self
+    }
+
+    pub fn permission_group(mut self, permission_group: &str) -> Self {
+        self.permission_group = Some(String::from(permission_group));
+        self
+    }
+
+    /// Build the `ServiceClient`.
+    ///
+    /// This fails if no credentials were given, or if there is no Directory url to look up the
+    /// services without an explicit url. A Directory url isn't needed if every service the client
+    /// has an interface for was given an explicit url.
+    pub fn build(self) -> Result<ServiceClient, BuilderError> {
+        let service_creds = self.service_creds.ok_or_else(|| BuilderError {
+            message: String::from("No service credentials were given."),
+        })?;
+
+        let needs_directory = CLIENT_SERVICES
+            .iter()
+            .any(|service| !self.service_urls.contains_key(service));
+        if needs_directory && !self.service_urls.contains_key(&ServiceType::Directory) {
+            return Err(BuilderError {
+                message: String::from(
+                    "No Directory url was given to look up the services without an explicit url.",
+                ),
+            });
+        }
+
+        let service_http = Arc::new(ServiceHttp::from(
+            &service_creds.service_username,
+            &service_creds.service_password,
+            Arc::new(self.http_client.unwrap_or_default()),
+        ));
+        for (service, service_urls) in self.service_urls {
+            for service_url in service_urls {
+                service_http.add_service_url(service.clone(), service_url);
+            }
+        }
+        for (service, selection) in self.provider_selection {
+            service_http.set_provider_selection(service, selection);
         }
+        if let Some(skew) = self.token_refresh_skew {
+            service_http.tokens().set_refresh_skew(skew);
+        }
+        if let Some(ttl) = self.discovery_ttl {
+            service_http.set_discovery_ttl(ttl);
+        }
+
+        Ok(ServiceClient::from_service_http(
+            service_http,
+            self.root_principle,
+            self.permission_group,
+        ))
     }
+}
 
-    pub async fn show_tokens(&self) {
-        println!("{:?}", self.tokens.lock().await);
+impl Default for ServiceClientBuilder {
+    fn default() -> Self {
+        ServiceClientBuilder::new()
     }
 }
 
@@ -291,6 +350,12 @@ pub struct ServiceCreds {
     service_password: String,
 }
 
+impl Default for ServiceCreds {
+    fn default() -> Self {
+        ServiceCreds::new()
+    }
+}
+
 impl ServiceCreds {
     pub fn new() -> Self {
         ServiceCreds {
@@ -310,14 +375,14 @@ impl ServiceCreds {
 pub mod utils {
     use http::header;
 
-    use crate::error::FetchError;
+    use crate::error::{FetchError, FetchErrorKind};
 
     /// Checks the validity of header values for the type of request.
     /// Returns a new reqwest::header::HeaderMap with valid headers.
     pub fn check_correct_headers(
         headers: &reqwest::header::HeaderMap,
         body: &Option<String>,
-        url: &String,
+        url: &str,
     ) -> Result<reqwest::header::HeaderMap, FetchError> {
         // Ensure headers are set correctly for the type of request
         let mut local_headers = headers.clone();
@@ -328,7 +393,8 @@ pub mod utils {
             } else {
                 return Err(FetchError {
                     message: String::from("Couldn't create correct header values."),
-                    url: url.clone(),
+                    url: String::from(url),
+                    kind: FetchErrorKind::Other,
                 });
             }
         });
@@ -340,7 +406,8 @@ pub mod utils {
                 } else {
                     return Err(FetchError {
                         message: String::from("Couldn't create correct header values."),
-                        url: url.clone(),
+                        url: String::from(url),
+                        kind: FetchErrorKind::Other,
                     });
                 }
             });
@@ -357,9 +424,28 @@ pub enum ServiceType {
     Authentication,
     MQTT,
     CommandEscalation,
+    Git,
+    Clusters,
+    /// Any other service advertised in the Directory, identified by its service UUID.
+    Other(uuid::Uuid),
 }
 
 impl ServiceType {
+    /// Get the service type for a service UUID. UUIDs of the well known services map to their
+    /// own variant, anything else to `ServiceType::Other`.
+    pub fn from_uuid(service: uuid::Uuid) -> Self {
+        match service {
+            uuids::service::DIRECTORY => ServiceType::Directory,
+            uuids::service::CONFIG_DB => ServiceType::ConfigDb,
+            uuids::service::AUTHENTICATION => ServiceType::Authentication,
+            uuids::service::MQTT => ServiceType::MQTT,
+            uuids::service::COMMAND_ESCALATION => ServiceType::CommandEscalation,
+            uuids::service::GIT => ServiceType::Git,
+            uuids::service::CLUSTERS => ServiceType::Clusters,
+            other => ServiceType::Other(other),
+        }
+    }
+
     pub fn to_uuid(&self) -> uuid::Uuid {
         match &self {
             ServiceType::Directory => uuids::service::DIRECTORY,
@@ -367,6 +453,9 @@ impl ServiceType {
             ServiceType::Authentication => uuids::service::AUTHENTICATION,
             ServiceType::MQTT => uuids::service::MQTT,
             ServiceType::CommandEscalation => uuids::service::COMMAND_ESCALATION,
+            ServiceType::Git => uuids::service::GIT,
+            ServiceType::Clusters => uuids::service::CLUSTERS,
+            ServiceType::Other(service) => *service,
         }
     }
 }
@@ -382,12 +471,21 @@ impl Display for ServiceType {
                 ServiceType::Authentication => "Authentication",
                 ServiceType::MQTT => "MQTT",
                 ServiceType::CommandEscalation => "CommandEscalation",
+                ServiceType::Git => "Git",
+                ServiceType::Clusters => "Clusters",
+                ServiceType::Other(_) => "Other",
             },
             self.to_uuid()
         )
     }
 }
 
+impl From<uuid::Uuid> for ServiceType {
+    fn from(service: uuid::Uuid) -> Self {
+        ServiceType::from_uuid(service)
+    }
+}
+
 pub mod request {
     //! Contains request representations and implementations.
     use std::collections::HashMap;
@@ -404,6 +502,12 @@ pub mod request {
         pub body: Option<String>,
     }
 
+    impl Default for FetchOpts {
+        fn default() -> Self {
+            FetchOpts::new()
+        }
+    }
+
     impl FetchOpts {
         pub fn new() -> Self {
             FetchOpts {
@@ -447,17 +551,25 @@ pub mod request {
 
 pub mod response {
     //! Contains response representations and implementations.
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
     use serde::Deserialize;
 
     #[derive(Debug)]
     pub struct FetchResponse {
         pub status: http::StatusCode,
         pub content: String,
+        /// The response's ETag header, if it had one.
+        pub etag: Option<String>,
     }
 
     impl FetchResponse {
         pub fn from(status: http::StatusCode, content: String) -> Self {
-            FetchResponse { status, content }
+            FetchResponse {
+                status,
+                content,
+                etag: None,
+            }
         }
     }
 
@@ -492,6 +604,46 @@ pub mod response {
         pub fn from(token: String, expiry: u64) -> Self {
             TokenStruct { token, expiry }
         }
+
+        /// Check whether the token expires within `skew` of now. The expiry is in milliseconds
+        /// since the UNIX epoch.
+        pub fn expires_within(&self, skew: Duration) -> bool {
+            let now_millis = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_millis())
+                .unwrap_or_default();
+            u128::from(self.expiry) <= now_millis + skew.as_millis()
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        use super::TokenStruct;
+
+        fn token_expiring_in(from_now: Duration) -> TokenStruct {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            TokenStruct::from(String::new(), (now + from_now).as_millis() as u64)
+        }
+
+        #[test]
+        fn token_expiring_after_skew_is_valid() {
+            let token = token_expiring_in(Duration::from_secs(600));
+            assert!(!token.expires_within(Duration::from_secs(60)));
+        }
+
+        #[test]
+        fn token_expiring_within_skew_needs_refresh() {
+            let token = token_expiring_in(Duration::from_secs(30));
+            assert!(token.expires_within(Duration::from_secs(60)));
+        }
+
+        #[test]
+        fn expired_token_needs_refresh() {
+            let token = TokenStruct::from(String::new(), 0);
+            assert!(token.expires_within(Duration::ZERO));
+        }
     }
 }
 
@@ -501,15 +653,15 @@ pub(in crate::service) mod fetch_util {
 
     use serde_json;
 
-    use crate::error::FetchError;
+    use crate::error::{FetchError, FetchErrorKind};
     use crate::service::request::{FetchOpts, HttpRequestMethod};
     use crate::service::response::TokenStruct;
 
     pub(crate) async fn get_new_token(
         client: Arc<reqwest::Client>,
         service_url: String,
-        username: &String,
-        password: &String,
+        username: &str,
+        password: &str,
     ) -> Result<TokenStruct, FetchError> {
         let token_url = format!("{}/token", service_url);
         if let Ok(request) = client
@@ -517,20 +669,23 @@ pub(in crate::service) mod fetch_util {
             .basic_auth(username, Some(password))
             .build()
         {
-            if let Ok(response) = client.execute(request).await {
-                match response.status() {
+            match client.execute(request).await {
+                Ok(response) => match response.status() {
                     http::StatusCode::OK => try_decode_token(response, token_url).await,
                     http::StatusCode::UNAUTHORIZED => Err(FetchError {
                         message: String::from("Error fetching new token: 401 Unauthorised."),
                         url: token_url,
+                        kind: FetchErrorKind::Other,
                     }),
                     http::StatusCode::INTERNAL_SERVER_ERROR => Err(FetchError {
                         message: String::from("Error fetching new token - 500 Server error."),
                         url: token_url,
+                        kind: FetchErrorKind::Other,
                     }),
                     http::StatusCode::NOT_FOUND => Err(FetchError {
                         message: String::from("Error fetching new token: 404 Not found."),
                         url: token_url,
+                        kind: FetchErrorKind::Other,
                     }),
                     _ => Err(FetchError {
                         message: format!(
@@ -538,18 +693,21 @@ pub(in crate::service) mod fetch_util {
                             response.status().as_str()
                         ),
                         url: token_url,
+                        kind: FetchErrorKind::Other,
                     }),
-                }
-            } else {
-                Err(FetchError {
+                },
+                Err(e) if e.is_connect() => Err(FetchError::connection_failed(token_url)),
+                Err(_) => Err(FetchError {
                     message: String::from("Couldn't build token request."),
                     url: token_url,
-                })
+                    kind: FetchErrorKind::Other,
+                }),
             }
         } else {
             Err(FetchError {
                 message: String::from("Couldn't build a request to send for a token."),
                 url: token_url,
+                kind: FetchErrorKind::Other,
             })
         }
     }
@@ -566,12 +724,14 @@ pub(in crate::service) mod fetch_util {
                     Err(FetchError {
                         message: String::from("Couldn't decode response into token"),
                         url: token_url,
+                        kind: FetchErrorKind::Other,
                     })
                 }
             }
             Err(_) => Err(FetchError {
                 message: String::from("No response body."),
                 url: token_url,
+                kind: FetchErrorKind::Other,
             }),
         }
     }
@@ -585,9 +745,108 @@ pub(in crate::service) mod fetch_util {
             (
                 opts.method == HttpRequestMethod::GET,
                 &opts.headers.is_empty(),
-                &opts.body.is_some()
+                &opts.body.is_none()
             ),
             (false, _, _) | (_, false, _) | (_, _, false)
         )
     }
+
+    #[cfg(test)]
+    mod tests {
+        use super::is_idempotent;
+        use crate::service::request::{FetchOpts, HttpRequestMethod};
+
+        #[test]
+        fn plain_get_is_idempotent() {
+            assert!(is_idempotent(&FetchOpts::new()));
+        }
+
+        #[test]
+        fn other_methods_are_not_idempotent() {
+            let opts = FetchOpts {
+                method: HttpRequestMethod::PUT,
+                ..FetchOpts::new()
+            };
+            assert!(!is_idempotent(&opts));
+        }
+
+        #[test]
+        fn get_with_body_or_headers_is_not_idempotent() {
+            let with_body = FetchOpts {
+                body: Some(String::from("{}")),
+                ..FetchOpts::new()
+            };
+            assert!(!is_idempotent(&with_body));
+
+            let mut with_headers = FetchOpts::new();
+            with_headers
+                .headers
+                .insert(http::header::IF_MATCH, http::HeaderValue::from_static("*"));
+            assert!(!is_idempotent(&with_headers));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_every_client_service(builder: ServiceClientBuilder) -> ServiceClientBuilder {
+        CLIENT_SERVICES.iter().fold(builder, |builder, service| {
+            builder.service_url(service.clone(), "http://service.example")
+        })
+    }
+
+    #[test]
+    fn build_needs_credentials() {
+        let result = with_every_client_service(ServiceClient::builder())
+            .directory_url("http://directory.example")
+            .build();
+
+        assert!(result.is_err_and(|e| e.message.contains("credentials")));
+    }
+
+    #[test]
+    fn build_needs_directory_for_services_without_url() {
+        let result = ServiceClient::builder()
+            .credentials("user", "pass")
+            .service_url(ServiceType::ConfigDb, "http://configdb.example")
+            .build();
+
+        assert!(result.is_err_and(|e| e.message.contains("Directory")));
+    }
+
+    #[test]
+    fn build_uses_directory_for_services_without_url() {
+        let client = ServiceClient::builder()
+            .credentials("user", "pass")
+            .directory_url("http://directory.example/")
+            .service_url(ServiceType::ConfigDb, "http://configdb.example")
+            .build()
+            .unwrap_or_else(|e| panic!("{}", e));
+        let service_http = client.service_http();
+
+        assert_eq!(
+            service_http.service_url(&ServiceType::Directory).as_deref(),
+            Some("http://directory.example")
+        );
+        assert_eq!(
+            service_http.service_url(&ServiceType::ConfigDb).as_deref(),
+            Some("http://configdb.example")
+        );
+        assert_eq!(service_http.service_url(&ServiceType::Git), None);
+    }
+
+    #[test]
+    fn build_without_directory_when_every_service_has_url() {
+        let client = with_every_client_service(ServiceClient::builder())
+            .credentials("user", "pass")
+            .build()
+            .unwrap_or_else(|e| panic!("{}", e));
+
+        assert_eq!(
+            client.service_http().service_url(&ServiceType::Directory),
+            None
+        );
+    }
 }
@@ -1,110 +1,671 @@
 //! This module provides an implementation of AuthInterface for interacting with the Factory+
 //! Auth service.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use tokio::sync::Mutex;
+use futures::future::try_join_all;
 
-use crate::service::response::TokenStruct;
+use crate::error::{AuthError, FetchError, FetchErrorKind};
+use crate::service::auth::auth_models::{
+    AceAction, Acl, FetchAclQuery, PostAceBody, PostPrincipalBody, PrincipalIdentifier,
+    PrincipalMapping, PrincipalMappingResponse,
+};
+use crate::service::configdb::ConfigDbInterface;
+use crate::service::request::{FetchOpts, HttpRequestMethod};
+use crate::service::response::FetchResponse;
+use crate::service::service_http::ServiceHttp;
 use crate::service::ServiceType;
+use crate::sparkplug::util::address::Address;
 
+pub mod acl_cache;
+
+/// The interface for the Factory+ Auth service.
 pub struct AuthInterface {
-    service_type: ServiceType,
-    service_username: String,
-    service_password: String,
-    http_client: Arc<reqwest::Client>,
-    directory_url: String,
-    pub service_url: String,
-    tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
+    service_http: Arc<ServiceHttp>,
+    config_db: ConfigDbInterface,
 }
 
 impl AuthInterface {
-    pub fn from(
-        service_username: String,
-        service_password: String,
-        http_client: Arc<reqwest::Client>,
-        directory_url: String,
-        service_url: String,
-        tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
-    ) -> Self {
+    /// Create a new `AuthInterface` which makes its requests through the given service HTTP layer.
+    pub fn from(service_http: Arc<ServiceHttp>) -> Self {
         AuthInterface {
-            service_type: ServiceType::Authentication,
-            service_username,
-            service_password,
-            http_client: Arc::clone(&http_client),
-            directory_url,
-            service_url,
-            tokens,
+            config_db: ConfigDbInterface::from(Arc::clone(&service_http)),
+            service_http,
+        }
+    }
+
+    /// Check whether a principal has been granted a permission on a target.
+    ///
+    /// The ACL is fetched for the permission group the permission belongs to, such as the
+    /// client's configured `permission_group`, and the permission is then looked up within it.
+    ///
+    /// The principal can be given as a Kerberos name or as a UUID. If `wildcard_allowed` is true,
+    /// an ACE granting the permission on the wildcard target (`uuids::special::NULL`) also counts.
+    /// ACEs targeting `uuids::special::SELF` are matched against the principal's UUID, which is
+    /// resolved through the Auth service if the principal was given as a Kerberos name.
+    pub async fn check_acl(
+        &self,
+        principal: &str,
+        permission_group: uuid::Uuid,
+        permission: uuid::Uuid,
+        target: uuid::Uuid,
+        wildcard_allowed: bool,
+    ) -> Result<bool, FetchError> {
+        let (acl, principal_uuid) = self
+            .fetch_acl_for_check(principal, permission_group)
+            .await?;
+
+        Ok(acl.permits(permission, target, wildcard_allowed, principal_uuid))
+    }
+
+    /// Fetch the ACL used to check a principal's permissions, along with the principal's UUID to
+    /// match ACEs targeting `uuids::special::SELF` against.
+    ///
+    /// The principal can be given as a Kerberos name or as a UUID. A Kerberos name is only
+    /// resolved through the Auth service if the ACL has a SELF ACE to match against.
+    async fn fetch_acl_for_check(
+        &self,
+        principal: &str,
+        permission_group: uuid::Uuid,
+    ) -> Result<(Acl, Option<uuid::Uuid>), FetchError> {
+        let maybe_principal_uuid = uuid::Uuid::parse_str(principal).ok();
+        let acl = self
+            .fetch_acl(principal, permission_group, maybe_principal_uuid.is_some())
+            .await?;
+
+        let principal_uuid = match maybe_principal_uuid {
+            None if acl.has_self_target() => {
+                self.resolve_principal(&PrincipalIdentifier::Kerberos(String::from(principal)))
+                    .await?
+            }
+            maybe_uuid => maybe_uuid,
+        };
+
+        Ok((acl, principal_uuid))
+    }
+
+    /// Fetch the ACL for a principal, restricted to the permissions in the given permission group.
+    ///
+    /// If `by_uuid` is true the principal is treated as a UUID, otherwise as a Kerberos name.
+    pub async fn fetch_acl(
+        &self,
+        principal: &str,
+        permission_group: uuid::Uuid,
+        by_uuid: bool,
+    ) -> Result<Acl, FetchError> {
+        let target_url = String::from("/authz/acl");
+
+        let opts = FetchOpts {
+            url: target_url.clone(),
+            service: ServiceType::Authentication,
+            method: HttpRequestMethod::GET,
+            headers: Default::default(),
+            query: Some(FetchAclQuery::from(principal, permission_group, by_uuid).to_query()),
+            body: None,
+        };
+
+        let res = self.fetch(opts).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                if let Ok(acl) = serde_json::from_str::<Acl>(&res.content) {
+                    Ok(acl)
+                } else {
+                    Err(FetchError {
+                        message: String::from("Couldn't parse response into an ACL."),
+                        url: target_url,
+                        kind: FetchErrorKind::Other,
+                    })
+                }
+            }
+            status => Err(FetchError {
+                message: format!("{}: Failed to read ACL for {}", status, principal),
+                url: target_url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// Resolve a principal identifier to the principal's UUID.
+    ///
+    /// A Kerberos name or Sparkplug address is looked up in the Auth service, and None is returned
+    /// if no principal matches it. A UUID is returned as given without asking the Auth service, so
+    /// use `find_principal` to check that the principal exists.
+    pub async fn resolve_principal(
+        &self,
+        identifier: &PrincipalIdentifier,
+    ) -> Result<Option<uuid::Uuid>, FetchError> {
+        match identifier {
+            PrincipalIdentifier::Uuid(uuid) => Ok(Some(*uuid)),
+            PrincipalIdentifier::Kerberos(kerberos) => {
+                self.find_principal_uuid(HashMap::from([(
+                    String::from("kerberos"),
+                    kerberos.clone(),
+                )]))
+                .await
+            }
+            PrincipalIdentifier::Sparkplug(address) => {
+                self.resolve_principal_by_address(address).await
+            }
+        }
+    }
+
+    /// Find the full principal mapping (UUID, Kerberos name and Sparkplug address) for a principal
+    /// identifier.
+    ///
+    /// Returns None if the Auth service has no principal matching the identifier.
+    pub async fn find_principal(
+        &self,
+        identifier: &PrincipalIdentifier,
+    ) -> Result<Option<PrincipalMapping>, FetchError> {
+        let principal = match self.resolve_principal(identifier).await? {
+            Some(principal) => principal,
+            None => return Ok(None),
+        };
+
+        let target_url = format!("/authz/principal/{}", principal);
+
+        let opts = FetchOpts {
+            url: target_url.clone(),
+            service: ServiceType::Authentication,
+            method: HttpRequestMethod::GET,
+            headers: Default::default(),
+            query: None,
+            body: None,
+        };
+
+        let res = self.fetch(opts).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                if let Ok(mapping) = serde_json::from_str::<PrincipalMappingResponse>(&res.content)
+                {
+                    Ok(Some(mapping.into()))
+                } else {
+                    Err(FetchError {
+                        message: String::from("Couldn't parse response into a principal mapping."),
+                        url: target_url,
+                        kind: FetchErrorKind::Other,
+                    })
+                }
+            }
+            http::status::StatusCode::NOT_FOUND => Ok(None),
+            status => Err(FetchError {
+                message: format!("{}: Failed to find principal {}", status, principal),
+                url: target_url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// Register the Kerberos name and Sparkplug address of an existing principal with the Auth
+    /// service.
+    pub async fn add_principal(&self, mapping: &PrincipalMapping) -> Result<(), AuthError> {
+        let target_url = String::from("/authz/principal");
+
+        let req_body =
+            serde_json::to_string(&PostPrincipalBody::from(mapping)).map_err(|_| FetchError {
+                message: String::from("Couldn't serialise principal request body."),
+                url: target_url.clone(),
+                kind: FetchErrorKind::Other,
+            })?;
+
+        let opts = FetchOpts {
+            url: target_url.clone(),
+            service: ServiceType::Authentication,
+            method: HttpRequestMethod::POST,
+            headers: Default::default(),
+            query: None,
+            body: Some(req_body),
+        };
+
+        let res = self.fetch(opts).await?;
+
+        if res.status.is_success() {
+            Ok(())
+        } else {
+            Err(AuthError::from_status(
+                res.status,
+                FetchError {
+                    message: format!(
+                        "{}: Failed to add principal {} ({})",
+                        res.status, mapping.uuid, mapping.kerberos
+                    ),
+                    url: target_url,
+                    kind: FetchErrorKind::Other,
+                },
+            ))
         }
     }
 
-    pub fn check_acl(&self) {
-        todo!()
+    /// Create a new principal: a ConfigDB object of the given class, registered with the Auth
+    /// service under its Kerberos name and Sparkplug address, and optionally added to a
+    /// permission group.
+    ///
+    /// If any Auth step fails, the ConfigDB object (and any principal mapping already registered)
+    /// is removed again before the error is returned.
+    pub async fn create_principal(
+        &self,
+        class: uuid::Uuid,
+        kerberos: &str,
+        sparkplug: Option<Address>,
+        permission_group: Option<uuid::Uuid>,
+    ) -> Result<PrincipalMapping, AuthError> {
+        let principal = self.config_db.create_object(class, None, false).await?;
+
+        let mapping = PrincipalMapping {
+            uuid: principal,
+            kerberos: String::from(kerberos),
+            sparkplug,
+        };
+
+        if let Err(e) = self.add_principal(&mapping).await {
+            // Rollback is best-effort; the Auth error is the one worth reporting.
+            let _ = self.config_db.delete_object(principal).await;
+            return Err(e);
+        }
+
+        if let Some(group) = permission_group {
+            if let Err(e) = self.add_to_group(group, principal).await {
+                let _ = self.delete_principal(principal).await;
+                let _ = self.config_db.delete_object(principal).await;
+                return Err(e);
+            }
+        }
+
+        Ok(mapping)
     }
 
-    pub fn fetch_acl(&self) {
-        todo!()
+    /// Grant a permission to a principal on a target.
+    pub async fn add_ace(
+        &self,
+        principal: uuid::Uuid,
+        permission: uuid::Uuid,
+        target: uuid::Uuid,
+    ) -> Result<(), AuthError> {
+        self.edit_ace(PostAceBody::from(
+            AceAction::Add,
+            principal,
+            permission,
+            target,
+        ))
+        .await
     }
 
-    pub fn resolve_principal(&self) {
-        todo!()
+    /// Revoke a permission previously granted to a principal on a target.
+    pub async fn delete_ace(
+        &self,
+        principal: uuid::Uuid,
+        permission: uuid::Uuid,
+        target: uuid::Uuid,
+    ) -> Result<(), AuthError> {
+        self.edit_ace(PostAceBody::from(
+            AceAction::Delete,
+            principal,
+            permission,
+            target,
+        ))
+        .await
     }
 
-    pub fn find_principal(&self) {
-        todo!()
+    /// Add a member (a principal or another group) to a permission group.
+    pub async fn add_to_group(
+        &self,
+        group: uuid::Uuid,
+        member: uuid::Uuid,
+    ) -> Result<(), AuthError> {
+        self.edit_group(HttpRequestMethod::PUT, group, member).await
     }
 
-    pub fn add_principal(&self) {
-        todo!()
+    /// Remove a member from a permission group.
+    pub async fn remove_from_group(
+        &self,
+        group: uuid::Uuid,
+        member: uuid::Uuid,
+    ) -> Result<(), AuthError> {
+        self.edit_group(HttpRequestMethod::DELETE, group, member)
+            .await
     }
 
-    pub fn create_principal(&self) {
-        todo!()
+    /// List the UUIDs of all groups known to the Auth service.
+    pub async fn list_groups(&self) -> Result<Vec<uuid::Uuid>, AuthError> {
+        self.fetch_uuid_list(String::from("/authz/group")).await
     }
 
-    pub fn add_ace(&self) {
-        todo!()
+    /// List the direct members of a permission group.
+    pub async fn group_members(&self, group: uuid::Uuid) -> Result<Vec<uuid::Uuid>, AuthError> {
+        self.fetch_uuid_list(format!("/authz/group/{}", group))
+            .await
     }
 
-    pub fn delete_ace(&self) {
-        todo!()
+    /// List every group a principal belongs to, either directly or through membership of another
+    /// group.
+    ///
+    /// The Auth service has no lookup from a member to its groups, so this lists every group and
+    /// fetches the members of each: one request plus one per group, with the member requests made
+    /// concurrently. Callers checking many principals should cache the result.
+    pub async fn principal_groups(
+        &self,
+        principal: uuid::Uuid,
+    ) -> Result<HashSet<uuid::Uuid>, AuthError> {
+        let groups = self.list_groups().await?;
+        let members = try_join_all(groups.iter().map(|group| self.group_members(*group))).await?;
+
+        // Build a map from each member to the groups it is directly in.
+        let mut member_of: HashMap<uuid::Uuid, Vec<uuid::Uuid>> = HashMap::new();
+        for (group, members) in groups.into_iter().zip(members) {
+            for member in members {
+                member_of.entry(member).or_default().push(group);
+            }
+        }
+
+        let mut groups = HashSet::new();
+        let mut to_visit = vec![principal];
+        while let Some(member) = to_visit.pop() {
+            for group in member_of.get(&member).into_iter().flatten() {
+                if groups.insert(*group) {
+                    to_visit.push(*group);
+                }
+            }
+        }
+
+        Ok(groups)
+    }
+
+    /// Resolve the principal owning a Sparkplug address. Device addresses resolve to the owner of
+    /// their node.
+    async fn resolve_principal_by_address(
+        &self,
+        address: &Address,
+    ) -> Result<Option<uuid::Uuid>, FetchError> {
+        self.find_principal_uuid(HashMap::from([(
+            String::from("sparkplug"),
+            format!("{}/{}", address.group, address.node),
+        )]))
+        .await
+    }
+
+    async fn delete_principal(&self, principal: uuid::Uuid) -> Result<(), AuthError> {
+        let target_url = format!("/authz/principal/{}", principal);
+
+        let opts = FetchOpts {
+            url: target_url.clone(),
+            service: ServiceType::Authentication,
+            method: HttpRequestMethod::DELETE,
+            headers: Default::default(),
+            query: None,
+            body: None,
+        };
+
+        let res = self.fetch(opts).await?;
+
+        if res.status.is_success() {
+            Ok(())
+        } else {
+            Err(AuthError::from_status(
+                res.status,
+                FetchError {
+                    message: format!("{}: Failed to delete principal {}", res.status, principal),
+                    url: target_url,
+                    kind: FetchErrorKind::Other,
+                },
+            ))
+        }
+    }
+
+    async fn edit_group(
+        &self,
+        method: HttpRequestMethod,
+        group: uuid::Uuid,
+        member: uuid::Uuid,
+    ) -> Result<(), AuthError> {
+        let target_url = format!("/authz/group/{}/{}", group, member);
+
+        let opts = FetchOpts {
+            url: target_url.clone(),
+            service: ServiceType::Authentication,
+            method,
+            headers: Default::default(),
+            query: None,
+            body: None,
+        };
+
+        let res = self.fetch(opts).await?;
+
+        if res.status.is_success() {
+            Ok(())
+        } else {
+            Err(AuthError::from_status(
+                res.status,
+                FetchError {
+                    message: format!(
+                        "{}: Failed to update membership of {} in group {}",
+                        res.status, member, group
+                    ),
+                    url: target_url,
+                    kind: FetchErrorKind::Other,
+                },
+            ))
+        }
     }
 
-    pub fn add_to_group(&self) {
-        todo!()
+    async fn fetch_uuid_list(&self, target_url: String) -> Result<Vec<uuid::Uuid>, AuthError> {
+        let opts = FetchOpts {
+            url: target_url.clone(),
+            service: ServiceType::Authentication,
+            method: HttpRequestMethod::GET,
+            headers: Default::default(),
+            query: None,
+            body: None,
+        };
+
+        let res = self.fetch(opts).await?;
+
+        if res.status == http::status::StatusCode::OK {
+            serde_json::from_str::<Vec<uuid::Uuid>>(&res.content).map_err(|_| {
+                AuthError::Fetch(FetchError {
+                    message: String::from("Couldn't parse response into a list of UUIDs."),
+                    url: target_url,
+                    kind: FetchErrorKind::Other,
+                })
+            })
+        } else {
+            Err(AuthError::from_status(
+                res.status,
+                FetchError {
+                    message: format!("{}: Failed to list UUIDs", res.status),
+                    url: target_url,
+                    kind: FetchErrorKind::Other,
+                },
+            ))
+        }
     }
 
-    pub fn remove_from_group(&self) {
-        todo!()
+    async fn find_principal_uuid(
+        &self,
+        query: HashMap<String, String>,
+    ) -> Result<Option<uuid::Uuid>, FetchError> {
+        let target_url = String::from("/authz/principal/find");
+
+        let opts = FetchOpts {
+            url: target_url.clone(),
+            service: ServiceType::Authentication,
+            method: HttpRequestMethod::GET,
+            headers: Default::default(),
+            query: Some(query),
+            body: None,
+        };
+
+        let res = self.fetch(opts).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                if let Ok(principal) = serde_json::from_str::<uuid::Uuid>(&res.content) {
+                    Ok(Some(principal))
+                } else {
+                    Err(FetchError {
+                        message: String::from("Couldn't parse response into a principal UUID."),
+                        url: target_url,
+                        kind: FetchErrorKind::Other,
+                    })
+                }
+            }
+            http::status::StatusCode::NOT_FOUND => Ok(None),
+            status => Err(FetchError {
+                message: format!("{}: Failed to resolve principal", status),
+                url: target_url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
     }
 
-    fn resolve_principal_by_address(&self) {
-        todo!()
+    async fn edit_ace(&self, ace_body: PostAceBody) -> Result<(), AuthError> {
+        let target_url = String::from("/authz/ace");
+
+        let req_body = serde_json::to_string(&ace_body).map_err(|_| FetchError {
+            message: String::from("Couldn't serialise ACE request body."),
+            url: target_url.clone(),
+            kind: FetchErrorKind::Other,
+        })?;
+
+        let opts = FetchOpts {
+            url: target_url.clone(),
+            service: ServiceType::Authentication,
+            method: HttpRequestMethod::POST,
+            headers: Default::default(),
+            query: None,
+            body: Some(req_body),
+        };
+
+        let res = self.fetch(opts).await?;
+
+        if res.status.is_success() {
+            Ok(())
+        } else {
+            Err(AuthError::from_status(
+                res.status,
+                FetchError {
+                    message: format!(
+                        "{}: Failed to {} ACE {} on {} for {}",
+                        res.status,
+                        ace_body.ace_action,
+                        ace_body.permission,
+                        ace_body.target,
+                        ace_body.principal
+                    ),
+                    url: target_url,
+                    kind: FetchErrorKind::Other,
+                },
+            ))
+        }
     }
 
-    fn edit_ace(&self) {
-        todo!()
+    async fn fetch(&self, fetch_opts: FetchOpts) -> Result<FetchResponse, FetchError> {
+        self.service_http.fetch(fetch_opts).await
     }
 }
 
 pub mod auth_models {
     //! Contains structs and implementations for modelling Auth requests and responses.
 
-    use crate::sparkplug::util::address::Address;
+    use std::collections::HashMap;
+    use std::fmt::{Display, Formatter};
+
+    use serde::{Deserialize, Serialize};
 
+    use crate::service::configdb::configdb_models::PrincipalConfig;
+    use crate::sparkplug::util::address::{Address, AddressType};
+    use crate::uuids;
+
+    #[derive(Serialize)]
     pub struct PostAceBody {
         pub permission: uuid::Uuid,
         pub target: uuid::Uuid,
+        #[serde(rename = "action")]
         pub ace_action: AceAction,
         pub principal: uuid::Uuid,
-        pub kerberos: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub kerberos: Option<String>,
     }
 
+    impl PostAceBody {
+        pub fn from(
+            ace_action: AceAction,
+            principal: uuid::Uuid,
+            permission: uuid::Uuid,
+            target: uuid::Uuid,
+        ) -> Self {
+            PostAceBody {
+                permission,
+                target,
+                ace_action,
+                principal,
+                kerberos: None,
+            }
+        }
+    }
+
+    /// The identifiers the Auth service holds for a principal. Principals which don't publish
+    /// over Sparkplug have no Sparkplug address.
+    #[derive(Clone)]
     pub struct PrincipalMapping {
         pub uuid: uuid::Uuid,
         pub kerberos: String,
-        pub sparkplug: Address,
+        pub sparkplug: Option<Address>,
+    }
+
+    /// A principal mapping as returned by the Auth service, with the Sparkplug address given as a
+    /// group and node ID.
+    #[derive(Deserialize)]
+    pub struct PrincipalMappingResponse {
+        pub uuid: uuid::Uuid,
+        pub kerberos: String,
+        #[serde(default)]
+        pub sparkplug: Option<PrincipalConfig>,
+    }
+
+    /// The body of a request registering a principal mapping with the Auth service.
+    #[derive(Serialize)]
+    pub struct PostPrincipalBody {
+        pub uuid: uuid::Uuid,
+        pub kerberos: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub sparkplug: Option<PrincipalConfig>,
+    }
+
+    impl From<&PrincipalMapping> for PostPrincipalBody {
+        fn from(value: &PrincipalMapping) -> Self {
+            PostPrincipalBody {
+                uuid: value.uuid,
+                kerberos: value.kerberos.clone(),
+                sparkplug: value.sparkplug.as_ref().map(|address| {
+                    PrincipalConfig::from(address.group.clone(), address.node.clone())
+                }),
+            }
+        }
+    }
+
+    impl From<PrincipalMappingResponse> for PrincipalMapping {
+        fn from(value: PrincipalMappingResponse) -> Self {
+            PrincipalMapping {
+                uuid: value.uuid,
+                kerberos: value.kerberos,
+                sparkplug: value.sparkplug.map(|config| Address {
+                    group: config.group_id,
+                    node: config.node_id,
+                    address_type: AddressType::Node,
+                }),
+            }
+        }
+    }
+
+    /// Any one of the identifiers a principal can be looked up by.
+    #[derive(Clone)]
+    pub enum PrincipalIdentifier {
+        Uuid(uuid::Uuid),
+        Kerberos(String),
+        Sparkplug(Address),
     }
 
     pub struct FetchAclQuery {
@@ -113,19 +674,140 @@ pub mod auth_models {
         by_uuid: bool,
     }
 
+    impl FetchAclQuery {
+        pub fn from(principal: &str, permission_group: uuid::Uuid, by_uuid: bool) -> Self {
+            FetchAclQuery {
+                principal: String::from(principal),
+                permission: permission_group.to_string(),
+                by_uuid,
+            }
+        }
+
+        /// Convert to the query parameters expected by the Auth service's ACL endpoint.
+        pub fn to_query(&self) -> HashMap<String, String> {
+            HashMap::from([
+                (String::from("principal"), self.principal.clone()),
+                (String::from("permission"), self.permission.clone()),
+                (String::from("by-uuid"), self.by_uuid.to_string()),
+            ])
+        }
+    }
+
+    /// A single access control entry granting a permission on a target.
+    ///
+    /// The Auth service omits the principal fields when returning the ACL for a single principal.
+    #[derive(Deserialize, Clone, Debug)]
     pub struct Ace {
-        permission: uuid::Uuid,
-        target: uuid::Uuid,
-        principal: uuid::Uuid,
-        kerberos: String,
+        pub permission: uuid::Uuid,
+        pub target: uuid::Uuid,
+        #[serde(default)]
+        pub principal: Option<uuid::Uuid>,
+        #[serde(default)]
+        pub kerberos: Option<String>,
     }
 
+    #[derive(Deserialize, Clone, Debug)]
+    #[serde(transparent)]
     pub struct Acl {
-        acl_vec: Vec<Acl>,
+        pub acl_vec: Vec<Ace>,
+    }
+
+    impl Acl {
+        /// Check whether this ACL grants a permission on a target.
+        ///
+        /// An ACE targeting `uuids::special::SELF` matches when the target is the principal
+        /// itself, which requires the principal's UUID. If `wildcard_allowed` is true, an ACE
+        /// targeting `uuids::special::NULL` matches any target.
+        pub fn permits(
+            &self,
+            permission: uuid::Uuid,
+            target: uuid::Uuid,
+            wildcard_allowed: bool,
+            principal: Option<uuid::Uuid>,
+        ) -> bool {
+            self.acl_vec.iter().any(|ace| {
+                ace.permission == permission
+                    && (ace.target == target
+                        || (wildcard_allowed && ace.target == uuids::special::NULL)
+                        || (ace.target == uuids::special::SELF && principal == Some(target)))
+            })
+        }
+
+        /// Whether any ACE in this ACL targets `uuids::special::SELF`.
+        pub fn has_self_target(&self) -> bool {
+            self.acl_vec
+                .iter()
+                .any(|ace| ace.target == uuids::special::SELF)
+        }
     }
 
+    #[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
     pub enum AceAction {
         Add,
         Delete,
     }
+
+    impl Display for AceAction {
+        fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+            write!(
+                f,
+                "{}",
+                match self {
+                    AceAction::Add => "add",
+                    AceAction::Delete => "delete",
+                }
+            )
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::{Ace, Acl};
+        use crate::uuids;
+
+        const PERMISSION: uuid::Uuid = uuid::uuid!("1a2b3c4d-0000-4000-8000-000000000001");
+        const OTHER_PERMISSION: uuid::Uuid = uuid::uuid!("1a2b3c4d-0000-4000-8000-000000000002");
+        const TARGET: uuid::Uuid = uuid::uuid!("1a2b3c4d-0000-4000-8000-000000000003");
+        const OTHER_TARGET: uuid::Uuid = uuid::uuid!("1a2b3c4d-0000-4000-8000-000000000004");
+
+        fn acl(target: uuid::Uuid) -> Acl {
+            Acl {
+                acl_vec: vec![Ace {
+                    permission: PERMISSION,
+                    target,
+                    principal: None,
+                    kerberos: None,
+                }],
+            }
+        }
+
+        #[test]
+        fn exact_target_is_permitted() {
+            let acl = acl(TARGET);
+
+            assert!(acl.permits(PERMISSION, TARGET, false, None));
+            assert!(!acl.permits(PERMISSION, OTHER_TARGET, false, None));
+            assert!(!acl.permits(OTHER_PERMISSION, TARGET, false, None));
+        }
+
+        #[test]
+        fn wildcard_target_only_counts_when_allowed() {
+            let acl = acl(uuids::special::NULL);
+
+            assert!(acl.permits(PERMISSION, TARGET, true, None));
+            assert!(!acl.permits(PERMISSION, TARGET, false, None));
+            assert!(!acl.permits(OTHER_PERMISSION, TARGET, true, None));
+        }
+
+        #[test]
+        fn self_target_needs_the_principal_uuid() {
+            let acl = acl(uuids::special::SELF);
+
+            assert!(acl.permits(PERMISSION, TARGET, false, Some(TARGET)));
+            assert!(!acl.permits(PERMISSION, OTHER_TARGET, false, Some(TARGET)));
+            assert!(!acl.permits(PERMISSION, TARGET, false, None));
+            assert!(acl.has_self_target());
+        }
+    }
 }
@@ -0,0 +1,277 @@
+//! This module provides AclCache, a TTL-aware cache of ACLs fetched through AuthInterface.
+//!
+//! Services which check permissions on every request or MQTT message should go through an
+//! AclCache rather than calling `AuthInterface::check_acl` directly.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::Mutex;
+
+use crate::error::FetchError;
+use crate::service::auth::auth_models::Acl;
+use crate::service::auth::AuthInterface;
+
+/// Cache key: the principal as passed to the Auth service, and the permission group.
+type AclKey = (String, uuid::Uuid);
+
+/// The cached ACL for a key, locked while it is being fetched.
+type AclSlot = Arc<Mutex<Option<AclEntry>>>;
+
+/// A cached ACL along with when it was fetched.
+struct AclEntry {
+    acl: Acl,
+    principal_uuid: Option<uuid::Uuid>,
+    fetched_at: Instant,
+}
+
+/// Memoises ACLs per principal and permission group for a fixed time-to-live.
+///
+/// Each key has its own slot, so concurrent lookups of the same key share a single fetch from the
+/// Auth service while lookups of other keys proceed independently. Slots whose ACL has expired are
+/// swept out at most once per TTL, so keys which are no longer looked up don't build up.
+pub struct AclCache {
+    ttl: Duration,
+    slots: Mutex<AclSlots>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+struct AclSlots {
+    slots: HashMap<AclKey, AclSlot>,
+    swept_at: Instant,
+}
+
+impl AclSlots {
+    /// Drop every slot whose ACL has expired or was never fetched. Slots which are in use by a
+    /// lookup are kept.
+    fn sweep(&mut self, ttl: Duration) {
+        self.slots.retain(|_, slot| {
+            if Arc::strong_count(slot) > 1 {
+                return true;
+            }
+            match slot.try_lock() {
+                Ok(entry) => entry
+                    .as_ref()
+                    .is_some_and(|entry| entry.fetched_at.elapsed() < ttl),
+                Err(_) => true,
+            }
+        });
+        self.swept_at = Instant::now();
+    }
+}
+
+impl AclCache {
+    /// Create a new, empty `AclCache` whose entries expire after `ttl`.
+    pub fn new(ttl: Duration) -> Self {
+        AclCache {
+            ttl,
+            slots: Mutex::new(AclSlots {
+                slots: HashMap::new(),
+                swept_at: Instant::now(),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Check whether a principal has been granted a permission on a target, using a cached ACL if
+    /// a fresh one is held.
+    ///
+    /// This follows the same rules as `AuthInterface::check_acl`, and the principal's resolved UUID
+    /// is cached along with the ACL. The ACL is cached per principal and permission group, so
+    /// checks of any permission in the group share one fetch.
+    pub async fn check_acl(
+        &self,
+        auth_interface: &AuthInterface,
+        principal: &str,
+        permission_group: uuid::Uuid,
+        permission: uuid::Uuid,
+        target: uuid::Uuid,
+        wildcard_allowed: bool,
+    ) -> Result<bool, FetchError> {
+        self.with_entry(
+            principal,
+            permission_group,
+            auth_interface.fetch_acl_for_check(principal, permission_group),
+            |entry| {
+                entry
+                    .acl
+                    .permits(permission, target, wildcard_allowed, entry.principal_uuid)
+            },
+        )
+        .await
+    }
+
+    /// Fetch the ACL for a principal and permission group, using a cached ACL if a fresh one is
+    /// held.
+    pub async fn fetch_acl(
+        &self,
+        auth_interface: &AuthInterface,
+        principal: &str,
+        permission_group: uuid::Uuid,
+    ) -> Result<Acl, FetchError> {
+        self.with_entry(
+            principal,
+            permission_group,
+            auth_interface.fetch_acl_for_check(principal, permission_group),
+            |entry| entry.acl.clone(),
+        )
+        .await
+    }
+
+    /// Drop every cached ACL for the given principal.
+    pub async fn invalidate(&self, principal: &str) {
+        self.slots
+            .lock()
+            .await
+            .slots
+            .retain(|(cached_principal, _), _| cached_principal != principal);
+    }
+
+    /// Drop every cached ACL.
+    pub async fn clear(&self) {
+        self.slots.lock().await.slots.clear();
+    }
+
+    /// The number of lookups answered from the cache.
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// The number of lookups which required a fetch from the Auth service.
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Call `f` with the cached entry for a principal and permission group, awaiting `fetch` to
+    /// fill the entry first if there is no fresh one.
+    async fn with_entry<T>(
+        &self,
+        principal: &str,
+        permission_group: uuid::Uuid,
+        fetch: impl Future<Output = Result<(Acl, Option<uuid::Uuid>), FetchError>>,
+        f: impl FnOnce(&AclEntry) -> T,
+    ) -> Result<T, FetchError> {
+        let slot = {
+            let mut slots = self.slots.lock().await;
+            if slots.swept_at.elapsed() >= self.ttl {
+                slots.sweep(self.ttl);
+            }
+            Arc::clone(
+                slots
+                    .slots
+                    .entry((String::from(principal), permission_group))
+                    .or_default(),
+            )
+        };
+
+        // Holding the slot lock across the fetch makes concurrent lookups of this key wait for
+        // this fetch rather than starting their own.
+        let mut locked_slot = slot.lock().await;
+
+        if let Some(entry) = locked_slot.as_ref() {
+            if entry.fetched_at.elapsed() < self.ttl {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(f(entry));
+            }
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+
+        let (acl, principal_uuid) = fetch.await?;
+
+        let entry = locked_slot.insert(AclEntry {
+            acl,
+            principal_uuid,
+            fetched_at: Instant::now(),
+        });
+
+        Ok(f(entry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use super::*;
+
+    const GROUP: uuid::Uuid = uuid::uuid!("5d1a7c2e-0000-4000-8000-000000000001");
+    const OTHER_GROUP: uuid::Uuid = uuid::uuid!("5d1a7c2e-0000-4000-8000-000000000002");
+
+    /// Look up an entry, counting how many times the ACL is fetched.
+    async fn lookup(
+        cache: &AclCache,
+        fetches: &AtomicUsize,
+        principal: &str,
+        permission_group: uuid::Uuid,
+    ) {
+        let fetch = async {
+            fetches.fetch_add(1, Ordering::Relaxed);
+            Ok((Acl { acl_vec: vec![] }, None))
+        };
+        cache
+            .with_entry(principal, permission_group, fetch, |_| ())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn fresh_entries_are_reused() {
+        let cache = AclCache::new(Duration::from_secs(60));
+        let fetches = AtomicUsize::new(0);
+
+        lookup(&cache, &fetches, "alice", GROUP).await;
+        lookup(&cache, &fetches, "alice", GROUP).await;
+        lookup(&cache, &fetches, "alice", OTHER_GROUP).await;
+
+        assert_eq!(fetches.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.misses(), 2);
+    }
+
+    #[tokio::test]
+    async fn expired_entries_are_fetched_again() {
+        let cache = AclCache::new(Duration::ZERO);
+        let fetches = AtomicUsize::new(0);
+
+        lookup(&cache, &fetches, "alice", GROUP).await;
+        lookup(&cache, &fetches, "alice", GROUP).await;
+
+        assert_eq!(fetches.load(Ordering::Relaxed), 2);
+        assert_eq!(cache.hits(), 0);
+        assert_eq!(cache.misses(), 2);
+    }
+
+    #[tokio::test]
+    async fn invalidate_only_drops_the_given_principal() {
+        let cache = AclCache::new(Duration::from_secs(60));
+        let fetches = AtomicUsize::new(0);
+
+        lookup(&cache, &fetches, "alice", GROUP).await;
+        lookup(&cache, &fetches, "bob", GROUP).await;
+        cache.invalidate("alice").await;
+        lookup(&cache, &fetches, "alice", GROUP).await;
+        lookup(&cache, &fetches, "bob", GROUP).await;
+
+        assert_eq!(fetches.load(Ordering::Relaxed), 3);
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.misses(), 3);
+    }
+
+    #[tokio::test]
+    async fn sweep_drops_expired_slots() {
+        let cache = AclCache::new(Duration::ZERO);
+        let fetches = AtomicUsize::new(0);
+
+        lookup(&cache, &fetches, "alice", GROUP).await;
+        lookup(&cache, &fetches, "bob", GROUP).await;
+
+        // Each lookup sweeps the expired slots left by earlier lookups.
+        assert_eq!(cache.slots.lock().await.slots.len(), 1);
+    }
+}
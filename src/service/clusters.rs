@@ -0,0 +1,244 @@
+//! This module provides an implementation of ClustersInterface for interacting with the Factory+
+//! Clusters service, which manages edge cluster definitions and their bootstrap material.
+
+use std::sync::Arc;
+
+use crate::error::{FetchError, FetchErrorKind};
+use crate::service::clusters::clusters_models::{ClusterCreated, ClusterDefinition, ClusterStatus};
+use crate::service::request::{FetchOpts, HttpRequestMethod};
+use crate::service::response::FetchResponse;
+use crate::service::service_http::ServiceHttp;
+use crate::service::ServiceType;
+
+/// The interface for the Factory+ Clusters service.
+pub struct ClustersInterface {
+    pub service_type: ServiceType,
+    service_http: Arc<ServiceHttp>,
+}
+
+impl ClustersInterface {
+    /// Create a new `ClustersInterface` which makes its requests through the given service HTTP layer.
+    pub fn from(service_http: Arc<ServiceHttp>) -> Self {
+        ClustersInterface {
+            service_type: ServiceType::Clusters,
+            service_http,
+        }
+    }
+
+    /// List the UUIDs of every cluster known to the Clusters service.
+    pub async fn list_clusters(&self) -> Result<Vec<uuid::Uuid>, FetchError> {
+        let url = String::from("/v1/cluster");
+        let res = self.get(&url).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                serde_json::from_str(&res.content).map_err(|_| FetchError {
+                    message: String::from("Failed to parse a UUID from response."),
+                    url,
+                    kind: FetchErrorKind::Other,
+                })
+            }
+            _ => Err(FetchError {
+                message: format!("{}: Can't list clusters.", res.status),
+                url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// Get the definition of a cluster, if it exists.
+    pub async fn get_cluster(
+        &self,
+        cluster: uuid::Uuid,
+    ) -> Result<Option<ClusterDefinition>, FetchError> {
+        let url = format!("/v1/cluster/{}", cluster);
+        let res = self.get(&url).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                serde_json::from_str(&res.content)
+                    .map(Some)
+                    .map_err(|_| FetchError {
+                        message: String::from("Couldn't parse response into a cluster definition."),
+                        url,
+                        kind: FetchErrorKind::Other,
+                    })
+            }
+            http::status::StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(FetchError {
+                message: format!("{}: Can't get cluster {}.", res.status, cluster),
+                url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// Find a cluster by its name.
+    pub async fn find_cluster(&self, name: &str) -> Result<Option<uuid::Uuid>, FetchError> {
+        for cluster in self.list_clusters().await? {
+            if let Some(definition) = self.get_cluster(cluster).await? {
+                if definition.name == name {
+                    return Ok(Some(cluster));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Create a new cluster from its definition, returning the cluster's UUID.
+    pub async fn create_cluster(
+        &self,
+        definition: &ClusterDefinition,
+    ) -> Result<uuid::Uuid, FetchError> {
+        let url = String::from("/v1/cluster");
+        let body = serde_json::to_string(definition).map_err(|_| FetchError {
+            message: String::from("Couldn't serialise cluster definition."),
+            url: url.clone(),
+            kind: FetchErrorKind::Other,
+        })?;
+
+        let opts = FetchOpts {
+            url: url.clone(),
+            service: ServiceType::Clusters,
+            method: HttpRequestMethod::POST,
+            headers: Default::default(),
+            query: None,
+            body: Some(body),
+        };
+
+        let res = self.fetch(opts).await?;
+
+        match res.status {
+            http::status::StatusCode::OK | http::status::StatusCode::CREATED => {
+                serde_json::from_str::<ClusterCreated>(&res.content)
+                    .map(|created| created.uuid)
+                    .map_err(|_| FetchError {
+                        message: String::from("Couldn't parse response into a cluster UUID."),
+                        url,
+                        kind: FetchErrorKind::Other,
+                    })
+            }
+            _ => Err(FetchError {
+                message: format!(
+                    "{}: Creating cluster {} failed.",
+                    res.status, definition.name
+                ),
+                url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// Get the bootstrap script used to install a cluster on its edge hosts.
+    pub async fn bootstrap(&self, cluster: uuid::Uuid) -> Result<String, FetchError> {
+        let url = format!("/v1/cluster/{}/bootstrap", cluster);
+        let res = self.get(&url).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => Ok(res.content),
+            _ => Err(FetchError {
+                message: format!(
+                    "{}: Can't get bootstrap for cluster {}.",
+                    res.status, cluster
+                ),
+                url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// Get the bootstrap script for the cluster with the given name.
+    pub async fn bootstrap_by_name(&self, name: &str) -> Result<String, FetchError> {
+        match self.find_cluster(name).await? {
+            Some(cluster) => self.bootstrap(cluster).await,
+            None => Err(FetchError {
+                message: format!("No cluster named {}.", name),
+                url: String::from("/v1/cluster"),
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// Get the status of a cluster, if it exists.
+    pub async fn get_status(
+        &self,
+        cluster: uuid::Uuid,
+    ) -> Result<Option<ClusterStatus>, FetchError> {
+        let url = format!("/v1/cluster/{}/status", cluster);
+        let res = self.get(&url).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                serde_json::from_str(&res.content)
+                    .map(Some)
+                    .map_err(|_| FetchError {
+                        message: String::from("Couldn't parse response into a cluster status."),
+                        url,
+                        kind: FetchErrorKind::Other,
+                    })
+            }
+            http::status::StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(FetchError {
+                message: format!("{}: Can't get status of cluster {}.", res.status, cluster),
+                url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    async fn get(&self, url: &str) -> Result<FetchResponse, FetchError> {
+        let opts = FetchOpts {
+            url: String::from(url),
+            service: ServiceType::Clusters,
+            method: HttpRequestMethod::GET,
+            headers: Default::default(),
+            query: None,
+            body: None,
+        };
+
+        self.fetch(opts).await
+    }
+
+    async fn fetch(&self, fetch_opts: FetchOpts) -> Result<FetchResponse, FetchError> {
+        self.service_http.fetch(fetch_opts).await
+    }
+}
+
+pub mod clusters_models {
+    //! Contains structs and implementations for representations of Clusters service elements.
+    use std::collections::HashMap;
+
+    /// The definition of an edge cluster.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct ClusterDefinition {
+        pub name: String,
+        pub namespace: String,
+    }
+
+    impl ClusterDefinition {
+        pub fn from(name: &str, namespace: &str) -> Self {
+            ClusterDefinition {
+                name: String::from(name),
+                namespace: String::from(namespace),
+            }
+        }
+    }
+
+    /// The status of an edge cluster as reported by the Clusters service.
+    ///
+    /// `ready` is true once the cluster has been bootstrapped and is running. Any further detail
+    /// the service reports is kept in `detail`.
+    #[derive(Debug, Clone, serde::Deserialize)]
+    pub struct ClusterStatus {
+        #[serde(default)]
+        pub ready: bool,
+        #[serde(flatten)]
+        pub detail: HashMap<String, serde_json::Value>,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub(crate) struct ClusterCreated {
+        pub uuid: uuid::Uuid,
+    }
+}
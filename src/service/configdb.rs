@@ -1,45 +1,34 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
+use futures::{Stream, TryStreamExt};
 use http::header;
-use tokio::sync::Mutex;
-
-use crate::error::FetchError;
-use crate::service;
-use crate::service::configdb::configdb_models::{ObjectRegistration, PrincipalConfig};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::error::{ConfigDbError, FetchError, FetchErrorKind};
+use crate::service::configdb::configdb_models::{
+    ConfigEntry, ConfigSchema, GeneralInfo, ObjectRegistration, Precondition, PrincipalConfig,
+    Registration, ServiceConfig, SparkplugAddressConfig,
+};
+use crate::service::configdb::notify::{ConfigChange, Subscription};
 use crate::service::request::{FetchOpts, HttpRequestMethod};
-use crate::service::response::{FetchResponse, TokenStruct};
-use crate::service::utils;
+use crate::service::response::FetchResponse;
+use crate::service::service_http::ServiceHttp;
 use crate::service::ServiceType;
+use crate::sparkplug::util::address::Address;
+use crate::uuids;
+
+pub mod notify;
 
 pub struct ConfigDbInterface {
-    service_type: ServiceType,
-    service_username: String,
-    service_password: String,
-    http_client: Arc<reqwest::Client>,
-    directory_url: String,
-    pub service_url: String,
-    tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
+    service_http: Arc<ServiceHttp>,
 }
 
 impl ConfigDbInterface {
-    pub fn from(
-        service_username: String,
-        service_password: String,
-        http_client: Arc<reqwest::Client>,
-        directory_url: String,
-        service_url: String,
-        tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
-    ) -> Self {
-        ConfigDbInterface {
-            service_type: ServiceType::ConfigDb,
-            service_username,
-            service_password,
-            http_client: Arc::clone(&http_client),
-            directory_url,
-            service_url,
-            tokens,
-        }
+    /// Create a new `ConfigDbInterface` which makes its requests through the given service HTTP layer.
+    pub fn from(service_http: Arc<ServiceHttp>) -> Self {
+        ConfigDbInterface { service_http }
     }
 
     pub async fn get_config(
@@ -47,7 +36,30 @@ impl ConfigDbInterface {
         app: uuid::Uuid,
         obj: uuid::Uuid,
     ) -> Result<Option<PrincipalConfig>, FetchError> {
-        let target_url = format!("{}/v1/app/{}/object/{}", self.service_url, app, obj);
+        self.get_config_as(app, obj).await
+    }
+
+    /// Get an object's config entry for an app, deserialised into `T`. Returns None if the object
+    /// has no entry for the app.
+    pub async fn get_config_as<T: DeserializeOwned>(
+        &self,
+        app: uuid::Uuid,
+        obj: uuid::Uuid,
+    ) -> Result<Option<T>, FetchError> {
+        Ok(self
+            .get_config_with_etag(app, obj)
+            .await?
+            .map(|entry| entry.value))
+    }
+
+    /// Get an object's config entry for an app along with its ETag, for use as a precondition
+    /// when writing the entry back. Returns None if the object has no entry for the app.
+    pub async fn get_config_with_etag<T: DeserializeOwned>(
+        &self,
+        app: uuid::Uuid,
+        obj: uuid::Uuid,
+    ) -> Result<Option<ConfigEntry<T>>, FetchError> {
+        let target_url = format!("/v1/app/{}/object/{}", app, obj);
 
         let opts = FetchOpts {
             url: target_url.clone(),
@@ -62,14 +74,21 @@ impl ConfigDbInterface {
 
         match res.status {
             http::status::StatusCode::OK => {
-                let principal_config_result: Result<PrincipalConfig, serde_json::Error> =
+                let config_result: Result<T, serde_json::Error> =
                     serde_json::from_str(&res.content);
-                if let Ok(principal_config) = principal_config_result {
-                    Ok(Some(principal_config))
+                if let Ok(config) = config_result {
+                    Ok(Some(ConfigEntry {
+                        value: config,
+                        etag: res.etag,
+                    }))
                 } else {
                     Err(FetchError {
-                        message: String::from("Couldn't parse response into a principal config."),
+                        message: format!(
+                            "Couldn't parse response into a {}.",
+                            std::any::type_name::<T>()
+                        ),
                         url: target_url,
+                        kind: FetchErrorKind::Other,
                     })
                 }
             }
@@ -77,26 +96,101 @@ impl ConfigDbInterface {
             _ => Err(FetchError {
                 message: String::from("Can't get object."),
                 url: target_url,
+                kind: FetchErrorKind::Other,
             }),
         }
     }
 
+    /// Get an object's config entry for an app as untyped JSON. Returns None if the object has no
+    /// entry for the app.
+    pub async fn get_config_value(
+        &self,
+        app: uuid::Uuid,
+        obj: uuid::Uuid,
+    ) -> Result<Option<serde_json::Value>, FetchError> {
+        self.get_config_as(app, obj).await
+    }
+
+    /// Get an object's registration: its class, owner, and whether it has been deleted.
+    pub async fn registration(&self, obj: uuid::Uuid) -> Result<Option<Registration>, FetchError> {
+        self.get_config_as(uuids::app::REGISTRATION, obj).await
+    }
+
+    /// Get an object's General Info entry.
+    pub async fn object_info(&self, obj: uuid::Uuid) -> Result<Option<GeneralInfo>, FetchError> {
+        self.get_config_as(uuids::app::INFO, obj).await
+    }
+
+    /// Get an object's name from its General Info entry.
+    pub async fn object_name(&self, obj: uuid::Uuid) -> Result<Option<String>, FetchError> {
+        Ok(self.object_info(obj).await?.map(|info| info.name))
+    }
+
+    /// Get the Sparkplug address an object publishes under.
+    pub async fn sparkplug_address(&self, obj: uuid::Uuid) -> Result<Option<Address>, FetchError> {
+        Ok(self
+            .get_config_as::<SparkplugAddressConfig>(uuids::app::SPARKPLUG_ADDRESS, obj)
+            .await?
+            .map(|config| config.to_address()))
+    }
+
+    /// Get the JSON Schema held by a config schema object.
+    pub async fn config_schema(&self, obj: uuid::Uuid) -> Result<Option<ConfigSchema>, FetchError> {
+        self.get_config_as(uuids::app::CONFIG_SCHEMA, obj).await
+    }
+
+    /// Get a service's config entry.
+    pub async fn service_config(
+        &self,
+        service: uuid::Uuid,
+    ) -> Result<Option<ServiceConfig>, FetchError> {
+        self.get_config_as(uuids::app::SERVICE_CONFIG, service)
+            .await
+    }
+
     pub async fn put_config(
         &self,
         app: uuid::Uuid,
         obj: uuid::Uuid,
         json_body: String,
     ) -> Result<FetchResponse, FetchError> {
-        let opts = FetchOpts {
-            url: format!("{}/v1/app/{}/object/{}", self.service_url, app, obj),
-            service: ServiceType::ConfigDb,
-            method: HttpRequestMethod::PUT,
-            headers: Default::default(),
-            query: Default::default(),
-            body: Some(json_body),
-        };
+        self.fetch(put_config_opts(app, obj, json_body)).await
+    }
 
-        self.fetch(opts).await
+    /// Replace an object's config entry for an app, only if the precondition holds.
+    ///
+    /// Fails with `ConfigDbError::PreconditionFailed` if the ConfigDB responds with a 412.
+    pub async fn put_config_if(
+        &self,
+        app: uuid::Uuid,
+        obj: uuid::Uuid,
+        json_body: String,
+        precondition: &Precondition,
+    ) -> Result<FetchResponse, ConfigDbError> {
+        self.fetch_if(put_config_opts(app, obj, json_body), precondition)
+            .await
+    }
+
+    /// Replace an object's config entry for an app with `config` serialised as JSON.
+    pub async fn put_config_from<T: Serialize>(
+        &self,
+        app: uuid::Uuid,
+        obj: uuid::Uuid,
+        config: &T,
+    ) -> Result<FetchResponse, FetchError> {
+        let json_body = serialise_config(app, obj, config)?;
+
+        self.put_config(app, obj, json_body).await
+    }
+
+    /// Replace an object's config entry for an app with untyped JSON.
+    pub async fn put_config_value(
+        &self,
+        app: uuid::Uuid,
+        obj: uuid::Uuid,
+        config: &serde_json::Value,
+    ) -> Result<FetchResponse, FetchError> {
+        self.put_config_from(app, obj, config).await
     }
 
     pub async fn delete_config(
@@ -104,16 +198,20 @@ impl ConfigDbInterface {
         app: uuid::Uuid,
         obj: uuid::Uuid,
     ) -> Result<FetchResponse, FetchError> {
-        let opts = FetchOpts {
-            url: format!("{}/v1/app/{}/object/{}", self.service_url, app, obj),
-            service: ServiceType::ConfigDb,
-            method: HttpRequestMethod::DELETE,
-            headers: Default::default(),
-            query: Default::default(),
-            body: None,
-        };
+        self.fetch(delete_config_opts(app, obj)).await
+    }
 
-        self.fetch(opts).await
+    /// Delete an object's config entry for an app, only if the precondition holds.
+    ///
+    /// Fails with `ConfigDbError::PreconditionFailed` if the ConfigDB responds with a 412.
+    pub async fn delete_config_if(
+        &self,
+        app: uuid::Uuid,
+        obj: uuid::Uuid,
+        precondition: &Precondition,
+    ) -> Result<FetchResponse, ConfigDbError> {
+        self.fetch_if(delete_config_opts(app, obj), precondition)
+            .await
     }
 
     pub async fn patch_config(
@@ -122,34 +220,65 @@ impl ConfigDbInterface {
         obj: uuid::Uuid,
         patch: String,
     ) -> Result<FetchResponse, FetchError> {
-        let target_url = format!("{}/v1/app/{}/object/{}", self.service_url, app, obj);
-
-        let header_val = {
-            let maybe_header_val = header::HeaderValue::from_str("application/merge-patch+json");
-            if let Ok(header_val) = maybe_header_val {
-                header_val
-            } else {
-                return Err(FetchError {
-                    message: String::from("Couldn't create correct header value."),
-                    url: target_url.clone(),
-                });
-            }
-        };
+        self.fetch(patch_config_opts(app, obj, patch)?).await
+    }
 
-        let opts = FetchOpts {
-            url: format!("/v1/app/{}/object/{}", app, obj),
-            service: ServiceType::ConfigDb,
-            method: HttpRequestMethod::PATCH,
-            headers: {
-                let mut headers: reqwest::header::HeaderMap = Default::default();
-                headers.insert(header::CONTENT_TYPE, header_val);
-                headers
-            },
-            query: Default::default(),
-            body: Some(patch),
-        };
+    /// Apply a JSON merge patch to an object's config entry for an app, only if the precondition
+    /// holds.
+    ///
+    /// Fails with `ConfigDbError::PreconditionFailed` if the ConfigDB responds with a 412.
+    pub async fn patch_config_if(
+        &self,
+        app: uuid::Uuid,
+        obj: uuid::Uuid,
+        patch: String,
+        precondition: &Precondition,
+    ) -> Result<FetchResponse, ConfigDbError> {
+        self.fetch_if(patch_config_opts(app, obj, patch)?, precondition)
+            .await
+    }
 
-        self.fetch(opts).await
+    /// Read, modify and write back an object's config entry for an app without losing concurrent
+    /// changes.
+    ///
+    /// `update` is given the current entry, or None if there is none, and returns the new entry.
+    /// The new entry is only written if the stored entry hasn't changed since it was read; if it
+    /// has, the entry is read again and `update` called again, up to `MAX_UPDATE_ATTEMPTS` times.
+    /// If the ConfigDB doesn't return an ETag for an existing entry, nothing is written and
+    /// `ConfigDbError::MissingEtag` is returned, since the write couldn't be made safely.
+    pub async fn update_config<T, F>(
+        &self,
+        app: uuid::Uuid,
+        obj: uuid::Uuid,
+        mut update: F,
+    ) -> Result<T, ConfigDbError>
+    where
+        T: DeserializeOwned + Serialize,
+        F: FnMut(Option<T>) -> T,
+    {
+        let target_url = format!("/v1/app/{}/object/{}", app, obj);
+
+        for _ in 0..MAX_UPDATE_ATTEMPTS {
+            let entry = self.get_config_with_etag(app, obj).await?;
+            let (current, precondition) = update_precondition(entry, &target_url)?;
+
+            let new_config = update(current);
+            let json_body = serialise_config(app, obj, &new_config)?;
+
+            let result = self.put_config_if(app, obj, json_body, &precondition).await;
+            if update_written(result, &target_url)? {
+                return Ok(new_config);
+            }
+        }
+
+        Err(ConfigDbError::PreconditionFailed(FetchError {
+            message: format!(
+                "Config kept changing; gave up after {} attempts.",
+                MAX_UPDATE_ATTEMPTS
+            ),
+            url: target_url,
+            kind: FetchErrorKind::Other,
+        }))
     }
 
     pub async fn create_object(
@@ -162,7 +291,7 @@ impl ConfigDbInterface {
         let maybe_req_body: Result<String, serde_json::Error> =
             serde_json::ser::to_string(&ObjectRegistration::from(obj_uuid, class));
 
-        let target_url = format!("{}/v1/object", self.service_url);
+        let target_url = String::from("/v1/object");
 
         if let Ok(req_body) = maybe_req_body {
             let opts = FetchOpts {
@@ -178,6 +307,7 @@ impl ConfigDbInterface {
                 Ok(res) if res.status == 200 && is_exclusive => Err(FetchError {
                     message: format!("Exclusive create of {} failed", obj_uuid),
                     url: target_url.clone(),
+                    kind: FetchErrorKind::Other,
                 }),
                 Ok(res) if res.status == 200 || res.status == 201 => {
                     let object_reg_result: Result<ObjectRegistration, serde_json::Error> =
@@ -190,16 +320,19 @@ impl ConfigDbInterface {
                                 "Couldn't parse response into an object registration.",
                             ),
                             url: target_url.clone(),
+                            kind: FetchErrorKind::Other,
                         })
                     }
                 }
                 Ok(res) if maybe_obj_uuid.is_some() => Err(FetchError {
                     message: format!("{}: Creating {} failed", res.status, obj_uuid),
                     url: target_url.clone(),
+                    kind: FetchErrorKind::Other,
                 }),
                 Ok(res) => Err(FetchError {
                     message: format!("{}: Creating new {} failed", res.status, class),
                     url: target_url.clone(),
+                    kind: FetchErrorKind::Other,
                 }),
                 Err(fetch_error) => Err(fetch_error),
             }
@@ -207,13 +340,14 @@ impl ConfigDbInterface {
             Err(FetchError {
                 message: String::from("Couldn't create an object registration."),
                 url: String::from("/v1/object"),
+                kind: FetchErrorKind::Other,
             })
         }
     }
 
     pub async fn delete_object(&self, obj: uuid::Uuid) -> Result<FetchResponse, FetchError> {
         let opts = FetchOpts {
-            url: format!("{}/v1/object/{}", self.service_url, obj),
+            url: format!("/v1/object/{}", obj),
             service: ServiceType::ConfigDb,
             method: HttpRequestMethod::DELETE,
             headers: Default::default(),
@@ -224,6 +358,134 @@ impl ConfigDbInterface {
         self.fetch(opts).await
     }
 
+    /// List the apps known to the ConfigDB.
+    pub async fn list_apps(&self) -> Result<HashSet<uuid::Uuid>, FetchError> {
+        self.list_uuids(String::from("/v1/app"), "apps").await
+    }
+
+    /// List every object known to the ConfigDB.
+    pub async fn list_objects(&self) -> Result<HashSet<uuid::Uuid>, FetchError> {
+        self.list_uuids(String::from("/v1/object"), "objects").await
+    }
+
+    /// List the objects which are members of a class, including members of its subclasses.
+    pub async fn list_class_members(
+        &self,
+        class: uuid::Uuid,
+    ) -> Result<HashSet<uuid::Uuid>, FetchError> {
+        self.list_uuids(
+            format!("/v2/class/{}/member", class),
+            &format!("members of {}", class),
+        )
+        .await
+    }
+
+    /// List the objects which are members of a class itself, excluding members of its
+    /// subclasses.
+    pub async fn list_direct_class_members(
+        &self,
+        class: uuid::Uuid,
+    ) -> Result<HashSet<uuid::Uuid>, FetchError> {
+        self.list_uuids(
+            format!("/v2/class/{}/direct/member", class),
+            &format!("direct members of {}", class),
+        )
+        .await
+    }
+
+    /// List the objects which have a config entry for an app.
+    pub async fn list_app_objects(
+        &self,
+        app: uuid::Uuid,
+    ) -> Result<HashSet<uuid::Uuid>, FetchError> {
+        self.list_uuids(
+            format!("/v1/app/{}/object", app),
+            &format!("objects of {}", app),
+        )
+        .await
+    }
+
+    /// Walk the config entries for an app, deserialised into `T`, one object at a time.
+    ///
+    /// The objects are listed up front, then each entry is only fetched as the stream is polled,
+    /// so large apps can be processed without holding every entry in memory. Objects whose entry
+    /// is removed while walking are skipped.
+    pub fn app_configs<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        app: uuid::Uuid,
+    ) -> impl Stream<Item = Result<(uuid::Uuid, T), FetchError>> + 'a {
+        futures::stream::once(self.list_app_objects(app))
+            .map_ok(|objs| futures::stream::iter(objs.into_iter().map(Ok)))
+            .try_flatten()
+            .try_filter_map(move |obj| async move {
+                Ok(self
+                    .get_config_as::<T>(app, obj)
+                    .await?
+                    .map(|config| (obj, config)))
+            })
+    }
+
+    /// Watch an object's config entry for an app, yielding each change to it.
+    ///
+    /// The watch runs over the ConfigDB notify WebSocket and reconnects if the connection drops,
+    /// so the stream only ends when it is dropped. The current entry is yielded first. Each failed
+    /// attempt to connect is yielded as an error, and the watch keeps retrying after it.
+    pub fn watch_config<T: DeserializeOwned + Send + 'static>(
+        &self,
+        app: uuid::Uuid,
+        obj: uuid::Uuid,
+    ) -> impl Stream<Item = Result<ConfigChange<T>, FetchError>> + Send + 'static {
+        notify::watch(
+            Arc::clone(&self.service_http),
+            Subscription::Config { app, obj },
+        )
+    }
+
+    /// Watch every config entry for an app, yielding each change to any of them.
+    ///
+    /// This behaves as `watch_config`, with the current entries yielded first. After reconnecting,
+    /// the current entries are yielded again, along with a deletion for each entry seen before
+    /// which no longer exists.
+    pub fn watch_app<T: DeserializeOwned + Send + 'static>(
+        &self,
+        app: uuid::Uuid,
+    ) -> impl Stream<Item = Result<ConfigChange<T>, FetchError>> + Send + 'static {
+        notify::watch(Arc::clone(&self.service_http), Subscription::App { app })
+    }
+
+    async fn list_uuids(
+        &self,
+        url: String,
+        list_kind: &str,
+    ) -> Result<HashSet<uuid::Uuid>, FetchError> {
+        let opts = FetchOpts {
+            url: url.clone(),
+            service: ServiceType::ConfigDb,
+            method: HttpRequestMethod::GET,
+            headers: Default::default(),
+            query: Default::default(),
+            body: None,
+        };
+
+        let res = self.fetch(opts).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                serde_json::from_str(&res.content).map_err(|_| FetchError {
+                    message: String::from("Failed to parse a UUID from response."),
+                    url: url.clone(),
+                    kind: FetchErrorKind::Other,
+                })
+            }
+            http::status::StatusCode::NOT_FOUND => Ok(HashSet::new()),
+            _ => Err(FetchError {
+                message: format!("{}: Can't list {}.", res.status, list_kind),
+                url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
     pub async fn search(
         &self,
         app: uuid::Uuid,
@@ -232,16 +494,11 @@ impl ConfigDbInterface {
         class: Option<String>,
     ) -> Result<Option<Vec<uuid::Uuid>>, FetchError> {
         let new_query: HashMap<String, String> = query
-            .into_iter()
+            .iter()
             .chain(results)
             .map(|(k, v)| (k.clone(), v.clone()))
             .collect();
-        let url = format!(
-            "{}/v1/app/{}{}/search",
-            self.service_url,
-            app,
-            class.unwrap_or_default()
-        );
+        let url = format!("/v1/app/{}{}/search", app, class.unwrap_or_default());
 
         let opts = FetchOpts {
             url: url.clone(),
@@ -264,6 +521,7 @@ impl ConfigDbInterface {
                     Err(FetchError {
                         message: String::from("Failed to parse a UUID from response."),
                         url: url.clone(),
+                        kind: FetchErrorKind::Other,
                     })
                 }
             }
@@ -271,6 +529,7 @@ impl ConfigDbInterface {
             _ => Err(FetchError {
                 message: String::from("ConfigDB search failed."),
                 url: url.clone(),
+                kind: FetchErrorKind::Other,
             }),
         }
     }
@@ -289,93 +548,161 @@ impl ConfigDbInterface {
             Some([_, _, ..]) => Err(FetchError {
                 message: format!("Returned more than once result: {} with {:?}", app, query),
                 url: format!("/v1/app/{}{}/search", app, class.unwrap_or_default()),
+                kind: FetchErrorKind::Other,
             }),
             // If the returned option is None or the Vec is somehow empty
             _ => Ok(None),
         }
     }
-    async fn fetch(&self, fetch_opts: FetchOpts) -> Result<FetchResponse, FetchError> {
-        let current_configdb_token = self.get_configdb_token().await?;
-
-        let headers =
-            utils::check_correct_headers(&fetch_opts.headers, &fetch_opts.body, &fetch_opts.url)?;
-
-        if let Ok(request) = match (fetch_opts.query, fetch_opts.body) {
-            (None, None) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers),
-            (Some(query), None) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers)
-                .query(&query),
-            (None, Some(body)) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers)
-                .body(body),
-            (Some(query), Some(body)) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers)
-                .query(&query)
-                .body(body),
-        }
-        .bearer_auth(current_configdb_token.token)
-        .build()
-        {
-            match self.http_client.execute(request).await {
-                Ok(response) => {
-                    let response_status = response.status();
-
-                    if let Ok(response_body) = response.text().await {
-                        Ok(FetchResponse {
-                            status: response_status,
-                            content: response_body,
-                        })
-                    } else {
-                        Err(FetchError {
-                            message: String::from("Couldn't decode response body."),
-                            url: fetch_opts.url,
-                        })
-                    }
-                }
-                _ => Err(FetchError {
-                    message: String::from("Couldn't make request."),
-                    url: fetch_opts.url,
-                }),
+
+    async fn fetch_if(
+        &self,
+        mut fetch_opts: FetchOpts,
+        precondition: &Precondition,
+    ) -> Result<FetchResponse, ConfigDbError> {
+        let (header_name, header_val) = precondition.to_header().ok_or_else(|| FetchError {
+            message: String::from("Couldn't create correct header value."),
+            url: fetch_opts.url.clone(),
+            kind: FetchErrorKind::Other,
+        })?;
+        fetch_opts.headers.insert(header_name, header_val);
+        let url = fetch_opts.url.clone();
+
+        let res = self.fetch(fetch_opts).await?;
+
+        match res.status {
+            http::status::StatusCode::PRECONDITION_FAILED => {
+                Err(ConfigDbError::PreconditionFailed(FetchError {
+                    message: format!("{}: Config has changed.", res.status),
+                    url,
+                    kind: FetchErrorKind::Other,
+                }))
             }
-        } else {
-            Err(FetchError {
-                message: String::from("Couldn't build a request to fetch."),
-                url: fetch_opts.url,
-            })
+            _ => Ok(res),
         }
     }
 
-    async fn get_configdb_token(&self) -> Result<TokenStruct, FetchError> {
-        let mut locked_tokens = self.tokens.lock().await;
-        // If we find a local token, return it. Otherwise, we request a new one.
-        if let Some(token) = locked_tokens.get(&ServiceType::Directory) {
-            Ok(token.clone())
+    async fn fetch(&self, fetch_opts: FetchOpts) -> Result<FetchResponse, FetchError> {
+        self.service_http.fetch(fetch_opts).await
+    }
+}
+
+/// How many times `ConfigDbInterface::update_config` reads and writes an entry before giving up.
+pub const MAX_UPDATE_ATTEMPTS: usize = 5;
+
+/// Split an entry read by `ConfigDbInterface::update_config` into its current value and the
+/// precondition for writing it back: that the entry is unchanged, or still absent if there was
+/// none. An existing entry without an ETag can't be written back safely, so is an error.
+fn update_precondition<T>(
+    entry: Option<ConfigEntry<T>>,
+    target_url: &str,
+) -> Result<(Option<T>, Precondition), ConfigDbError> {
+    match entry {
+        Some(ConfigEntry {
+            value,
+            etag: Some(etag),
+        }) => Ok((Some(value), Precondition::IfMatch(etag))),
+        Some(ConfigEntry { etag: None, .. }) => Err(ConfigDbError::MissingEtag(FetchError {
+            message: String::from("No ETag for the existing config."),
+            url: String::from(target_url),
+            kind: FetchErrorKind::Other,
+        })),
+        None => Ok((None, Precondition::if_absent())),
+    }
+}
+
+/// Whether a conditional write made by `ConfigDbInterface::update_config` was made. A failed
+/// precondition means the entry changed after it was read, so gives `Ok(false)` to read it and
+/// try again.
+fn update_written(
+    result: Result<FetchResponse, ConfigDbError>,
+    target_url: &str,
+) -> Result<bool, ConfigDbError> {
+    match result {
+        Ok(res) if res.status.is_success() => Ok(true),
+        Ok(res) => Err(ConfigDbError::Fetch(FetchError {
+            message: format!("{}: Couldn't write config.", res.status),
+            url: String::from(target_url),
+            kind: FetchErrorKind::Other,
+        })),
+        Err(ConfigDbError::PreconditionFailed(_)) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+fn serialise_config<T: Serialize>(
+    app: uuid::Uuid,
+    obj: uuid::Uuid,
+    config: &T,
+) -> Result<String, FetchError> {
+    serde_json::to_string(config).map_err(|_| FetchError {
+        message: format!("Couldn't serialise a {}.", std::any::type_name::<T>()),
+        url: format!("/v1/app/{}/object/{}", app, obj),
+        kind: FetchErrorKind::Other,
+    })
+}
+
+fn put_config_opts(app: uuid::Uuid, obj: uuid::Uuid, json_body: String) -> FetchOpts {
+    FetchOpts {
+        url: format!("/v1/app/{}/object/{}", app, obj),
+        service: ServiceType::ConfigDb,
+        method: HttpRequestMethod::PUT,
+        headers: Default::default(),
+        query: Default::default(),
+        body: Some(json_body),
+    }
+}
+
+fn delete_config_opts(app: uuid::Uuid, obj: uuid::Uuid) -> FetchOpts {
+    FetchOpts {
+        url: format!("/v1/app/{}/object/{}", app, obj),
+        service: ServiceType::ConfigDb,
+        method: HttpRequestMethod::DELETE,
+        headers: Default::default(),
+        query: Default::default(),
+        body: None,
+    }
+}
+
+fn patch_config_opts(
+    app: uuid::Uuid,
+    obj: uuid::Uuid,
+    patch: String,
+) -> Result<FetchOpts, FetchError> {
+    let target_url = format!("/v1/app/{}/object/{}", app, obj);
+
+    let header_val = {
+        let maybe_header_val = header::HeaderValue::from_str("application/merge-patch+json");
+        if let Ok(header_val) = maybe_header_val {
+            header_val
         } else {
-            let new_token = service::fetch_util::get_new_token(
-                Arc::clone(&self.http_client),
-                self.service_url.clone(),
-                &self.service_username,
-                &self.service_password,
-            )
-            .await?;
-            locked_tokens.insert(ServiceType::ConfigDb, new_token.clone());
-            Ok(new_token)
+            return Err(FetchError {
+                message: String::from("Couldn't create correct header value."),
+                url: target_url.clone(),
+                kind: FetchErrorKind::Other,
+            });
         }
-    }
+    };
+
+    Ok(FetchOpts {
+        url: target_url,
+        service: ServiceType::ConfigDb,
+        method: HttpRequestMethod::PATCH,
+        headers: {
+            let mut headers: reqwest::header::HeaderMap = Default::default();
+            headers.insert(header::CONTENT_TYPE, header_val);
+            headers
+        },
+        query: Default::default(),
+        body: Some(patch),
+    })
 }
 
 pub mod configdb_models {
     //! Contains structs and implementations for representations of Config elements.
 
+    use crate::sparkplug::util::address::{Address, AddressType};
+
     pub struct PutConfigBody {
         pub name: String,
         pub deleted: Option<bool>,
@@ -400,7 +727,7 @@ pub mod configdb_models {
         }
     }
 
-    #[derive(serde::Deserialize)]
+    #[derive(serde::Serialize, serde::Deserialize)]
     pub struct PrincipalConfig {
         pub group_id: String,
         pub node_id: String,
@@ -411,4 +738,205 @@ pub mod configdb_models {
             PrincipalConfig { group_id, node_id }
         }
     }
+
+    /// An object's entry in the Registration app.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct Registration {
+        pub uuid: Option<uuid::Uuid>,
+        pub class: uuid::Uuid,
+        pub owner: Option<uuid::Uuid>,
+        #[serde(default)]
+        pub deleted: bool,
+    }
+
+    /// An object's entry in the General Info app.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct GeneralInfo {
+        pub name: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub subclass: Option<uuid::Uuid>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub deleted: Option<bool>,
+    }
+
+    impl GeneralInfo {
+        pub fn from(name: &str) -> Self {
+            GeneralInfo {
+                name: String::from(name),
+                subclass: None,
+                deleted: None,
+            }
+        }
+    }
+
+    /// An object's entry in the Sparkplug Address app.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct SparkplugAddressConfig {
+        pub group_id: String,
+        pub node_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pub device_id: Option<String>,
+    }
+
+    impl SparkplugAddressConfig {
+        pub fn to_address(&self) -> Address {
+            Address {
+                group: self.group_id.clone(),
+                node: self.node_id.clone(),
+                address_type: match &self.device_id {
+                    Some(device_id) => AddressType::Device(device_id.clone()),
+                    None => AddressType::Node,
+                },
+            }
+        }
+    }
+
+    impl From<&Address> for SparkplugAddressConfig {
+        fn from(address: &Address) -> Self {
+            SparkplugAddressConfig {
+                group_id: address.group.clone(),
+                node_id: address.node.clone(),
+                device_id: match &address.address_type {
+                    AddressType::Device(device_id) => Some(device_id.clone()),
+                    AddressType::Node => None,
+                },
+            }
+        }
+    }
+
+    /// A config schema object's entry in the Config Schema app: a JSON Schema document.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    #[serde(transparent)]
+    pub struct ConfigSchema {
+        pub schema: serde_json::Value,
+    }
+
+    /// A service's entry in the Service Config app. Its shape is defined by each service.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    #[serde(transparent)]
+    pub struct ServiceConfig {
+        pub config: serde_json::Value,
+    }
+
+    impl ServiceConfig {
+        /// Deserialise the config into the service's own config type.
+        pub fn parse<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+            T::deserialize(&self.config)
+        }
+    }
+
+    /// A config entry along with the ETag the ConfigDB returned for it.
+    #[derive(Debug, Clone)]
+    pub struct ConfigEntry<T> {
+        pub value: T,
+        pub etag: Option<String>,
+    }
+
+    /// A precondition on a config write, checked by the ConfigDB against the entry's ETag.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Precondition {
+        /// Only write if the entry's ETag matches, i.e. it hasn't changed since it was read.
+        IfMatch(String),
+        /// Only write if the entry's ETag doesn't match. `"*"` matches any entry, so
+        /// `IfNoneMatch("*")` only writes if there is no entry yet.
+        IfNoneMatch(String),
+    }
+
+    impl Precondition {
+        /// A precondition which only holds if the entry doesn't exist yet.
+        pub fn if_absent() -> Self {
+            Precondition::IfNoneMatch(String::from("*"))
+        }
+
+        pub(crate) fn to_header(&self) -> Option<(http::HeaderName, http::HeaderValue)> {
+            let (header_name, etag) = match self {
+                Precondition::IfMatch(etag) => (http::header::IF_MATCH, etag),
+                Precondition::IfNoneMatch(etag) => (http::header::IF_NONE_MATCH, etag),
+            };
+            http::HeaderValue::from_str(etag)
+                .ok()
+                .map(|header_val| (header_name, header_val))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "/v1/app/x/object/y";
+
+    fn precondition_failed() -> ConfigDbError {
+        ConfigDbError::PreconditionFailed(FetchError {
+            message: String::new(),
+            url: String::from(URL),
+            kind: FetchErrorKind::Other,
+        })
+    }
+
+    #[test]
+    fn missing_entry_is_written_only_if_still_absent() {
+        let (current, precondition) = update_precondition::<u32>(None, URL).unwrap();
+
+        assert_eq!(current, None);
+        assert_eq!(precondition, Precondition::IfNoneMatch(String::from("*")));
+    }
+
+    #[test]
+    fn existing_entry_is_written_only_if_unchanged() {
+        let entry = ConfigEntry {
+            value: 1,
+            etag: Some(String::from("\"1\"")),
+        };
+        let (current, precondition) = update_precondition(Some(entry), URL).unwrap();
+
+        assert_eq!(current, Some(1));
+        assert_eq!(precondition, Precondition::IfMatch(String::from("\"1\"")));
+    }
+
+    #[test]
+    fn existing_entry_without_etag_is_refused() {
+        let entry = ConfigEntry {
+            value: 1,
+            etag: None,
+        };
+
+        assert!(matches!(
+            update_precondition(Some(entry), URL),
+            Err(ConfigDbError::MissingEtag(_))
+        ));
+    }
+
+    #[test]
+    fn successful_write_is_done() {
+        for status in [http::StatusCode::OK, http::StatusCode::NO_CONTENT] {
+            let result = Ok(FetchResponse::from(status, String::new()));
+            assert!(update_written(result, URL).unwrap());
+        }
+    }
+
+    #[test]
+    fn failed_precondition_is_retried() {
+        assert!(!update_written(Err(precondition_failed()), URL).unwrap());
+    }
+
+    #[test]
+    fn other_failures_are_returned() {
+        let result = Ok(FetchResponse::from(
+            http::StatusCode::INTERNAL_SERVER_ERROR,
+            String::new(),
+        ));
+        assert!(matches!(
+            update_written(result, URL),
+            Err(ConfigDbError::Fetch(_))
+        ));
+
+        let result = Err(ConfigDbError::Fetch(FetchError::connection_failed(
+            String::from(URL),
+        )));
+        assert!(matches!(
+            update_written(result, URL),
+            Err(ConfigDbError::Fetch(e)) if e.is_connection_failure()
+        ));
+    }
 }
@@ -0,0 +1,567 @@
+//! This module provides change notifications from the ConfigDB over its notify WebSocket.
+//!
+//! A watch opens a WebSocket to the ConfigDB, authenticated with the ConfigDB token from the shared
+//! token store, and subscribes to an object's config entry or to every entry for an app. Each
+//! change is yielded as a `ConfigChange`. If the connection drops, it is reopened and the
+//! subscription made again, so a watch only ends when its stream is dropped. Each failed attempt to
+//! connect is yielded as an error, and the next attempt is made after a growing delay. The current
+//! entries are yielded again after reconnecting, so changes made while disconnected aren't missed.
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::{SinkExt, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::error::{FetchError, FetchErrorKind};
+use crate::service::service_http::ServiceHttp;
+use crate::service::ServiceType;
+
+/// How long to wait before the first reconnection attempt. This doubles after every failed
+/// attempt, up to `MAX_RECONNECT_DELAY`.
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+type NotifySocket = WebSocketStream<MaybeTlsStream<tokio::net::TcpStream>>;
+
+/// A change to a watched config entry.
+#[derive(Debug, Clone)]
+pub struct ConfigChange<T> {
+    /// The object whose config entry changed.
+    pub obj: uuid::Uuid,
+    /// The new config entry, or None if it was deleted.
+    pub value: Option<T>,
+    /// The ETag of the new config entry, if the ConfigDB sent one.
+    pub etag: Option<String>,
+}
+
+impl<T> ConfigChange<T> {
+    pub fn is_deleted(&self) -> bool {
+        self.value.is_none()
+    }
+}
+
+/// What a watch is subscribed to.
+#[derive(Clone)]
+pub(crate) enum Subscription {
+    /// One object's config entry for an app.
+    Config { app: uuid::Uuid, obj: uuid::Uuid },
+    /// Every config entry for an app.
+    App { app: uuid::Uuid },
+}
+
+impl Subscription {
+    fn to_request(&self, session: uuid::Uuid) -> notify_models::NotifyRequest {
+        let (method, url) = match self {
+            Subscription::Config { app, obj } => {
+                ("WATCH", format!("v1/app/{}/object/{}", app, obj))
+            }
+            Subscription::App { app } => ("SEARCH", format!("v1/app/{}/object/", app)),
+        };
+
+        notify_models::NotifyRequest {
+            method: String::from(method),
+            uuid: session,
+            request: notify_models::NotifyHttpRequest {
+                method: String::from("GET"),
+                url,
+            },
+        }
+    }
+
+    fn watched_obj(&self) -> Option<uuid::Uuid> {
+        match self {
+            Subscription::Config { obj, .. } => Some(*obj),
+            Subscription::App { .. } => None,
+        }
+    }
+}
+
+struct WatchState<T> {
+    service_http: Arc<ServiceHttp>,
+    subscription: Subscription,
+    session: uuid::Uuid,
+    socket: Option<NotifySocket>,
+    reconnect_delay: Duration,
+    connect_failed: bool,
+    refresh_token: bool,
+    /// The objects which currently have a config entry, as far as the updates so far show.
+    known: HashSet<uuid::Uuid>,
+    /// Changes decoded from an update which haven't been yielded yet.
+    pending: VecDeque<Result<ConfigChange<T>, FetchError>>,
+}
+
+/// Watch a subscription, reconnecting whenever the WebSocket drops.
+///
+/// A failure to connect is yielded as an error before the next attempt, so a watch which can never
+/// connect, such as one with rejected credentials, doesn't wait silently.
+pub(crate) fn watch<T: DeserializeOwned + Send + 'static>(
+    service_http: Arc<ServiceHttp>,
+    subscription: Subscription,
+) -> impl Stream<Item = Result<ConfigChange<T>, FetchError>> + Send + 'static {
+    let state: WatchState<T> = WatchState {
+        service_http,
+        subscription,
+        session: uuid::Uuid::new_v4(),
+        socket: None,
+        reconnect_delay: MIN_RECONNECT_DELAY,
+        connect_failed: false,
+        refresh_token: false,
+        known: HashSet::new(),
+        pending: VecDeque::new(),
+    };
+
+    futures::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(item) = state.pending.pop_front() {
+                return Some((item, state));
+            }
+
+            let Some(socket) = state.socket.as_mut() else {
+                if state.connect_failed {
+                    tokio::time::sleep(state.reconnect_delay).await;
+                    state.reconnect_delay = (state.reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+                match connect(&mut state).await {
+                    Ok(socket) => {
+                        state.socket = Some(socket);
+                        state.connect_failed = false;
+                        state.reconnect_delay = MIN_RECONNECT_DELAY;
+                    }
+                    // The caller sees the failure, and the next poll waits before trying again.
+                    Err(e) => {
+                        state.connect_failed = true;
+                        return Some((Err(e), state));
+                    }
+                }
+                continue;
+            };
+
+            match socket.next().await {
+                Some(Ok(Message::Text(text))) => {
+                    let changes = decode_update(&state.subscription, &mut state.known, &text);
+                    state.pending.extend(changes);
+                }
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => state.socket = None,
+                // Pings are answered by tungstenite; nothing else is expected.
+                Some(Ok(_)) => {}
+            }
+        }
+    })
+}
+
+/// Open the notify socket and subscribe. If the ConfigDB rejects the token, the next attempt
+/// uses a new one.
+async fn connect<T>(state: &mut WatchState<T>) -> Result<NotifySocket, FetchError> {
+    let service_url = state
+        .service_http
+        .resolve_service_url(&ServiceType::ConfigDb)
+        .await?;
+    let token = if state.refresh_token {
+        state
+            .service_http
+            .refresh_token(ServiceType::ConfigDb)
+            .await?
+    } else {
+        state.service_http.get_token(ServiceType::ConfigDb).await?
+    };
+
+    let notify_url = format!(
+        "{}/notify/v2",
+        service_url
+            .replacen("https://", "wss://", 1)
+            .replacen("http://", "ws://", 1)
+    );
+    let socket_error = |e: tungstenite::Error| FetchError {
+        message: format!("Couldn't open notify socket: {}", e),
+        url: notify_url.clone(),
+        kind: FetchErrorKind::Other,
+    };
+
+    let mut request = notify_url
+        .as_str()
+        .into_client_request()
+        .map_err(socket_error)?;
+    let auth_header =
+        http::HeaderValue::from_str(&format!("Bearer {}", token.token)).map_err(|_| {
+            FetchError {
+                message: String::from("Couldn't create correct header values."),
+                url: notify_url.clone(),
+                kind: FetchErrorKind::Other,
+            }
+        })?;
+    request
+        .headers_mut()
+        .insert(http::header::AUTHORIZATION, auth_header);
+
+    let mut socket = match tokio_tungstenite::connect_async(request).await {
+        Ok((socket, _)) => socket,
+        Err(tungstenite::Error::Http(response))
+            if response.status() == http::StatusCode::UNAUTHORIZED =>
+        {
+            state.refresh_token = true;
+            return Err(FetchError {
+                message: format!("{}: Notify socket rejected the token.", response.status()),
+                url: notify_url,
+                kind: FetchErrorKind::Other,
+            });
+        }
+        Err(e) => return Err(socket_error(e)),
+    };
+    state.refresh_token = false;
+
+    let subscribe =
+        serde_json::to_string(&state.subscription.to_request(state.session)).map_err(|_| {
+            FetchError {
+                message: String::from("Couldn't serialise notify subscription."),
+                url: notify_url.clone(),
+                kind: FetchErrorKind::Other,
+            }
+        })?;
+    socket
+        .send(Message::Text(subscribe))
+        .await
+        .map_err(socket_error)?;
+
+    Ok(socket)
+}
+
+/// Decode an update from the notify socket into the changes it describes. Updates which don't
+/// describe a change, such as the acknowledgement of an app subscription, give no changes.
+///
+/// A full-state update lists every current entry of an app subscription. Any object in `known`
+/// which it doesn't list has had its entry deleted, so a deletion is given for it too. `known` is
+/// kept up to date with the objects which have an entry.
+fn decode_update<T: DeserializeOwned>(
+    subscription: &Subscription,
+    known: &mut HashSet<uuid::Uuid>,
+    text: &str,
+) -> Vec<Result<ConfigChange<T>, FetchError>> {
+    let update: notify_models::NotifyUpdate = match serde_json::from_str(text) {
+        Ok(update) => update,
+        Err(_) => {
+            return vec![Err(FetchError {
+                message: String::from("Couldn't decode notify update."),
+                url: String::from("/notify/v2"),
+                kind: FetchErrorKind::Other,
+            })]
+        }
+    };
+
+    if update.status >= 400 {
+        return vec![Err(FetchError {
+            message: format!("{}: ConfigDB rejected the subscription.", update.status),
+            url: String::from("/notify/v2"),
+            kind: FetchErrorKind::Other,
+        })];
+    }
+
+    if let Some(children) = update.children {
+        let mut changes: Vec<_> = known
+            .iter()
+            .filter(|obj| !children.contains_key(obj))
+            .map(|obj| {
+                Ok(ConfigChange {
+                    obj: *obj,
+                    value: None,
+                    etag: None,
+                })
+            })
+            .collect();
+        known.clear();
+        changes.extend(
+            children
+                .into_iter()
+                .map(|(obj, response)| decode_response(known, obj, response)),
+        );
+        return changes;
+    }
+
+    match (update.child.or(subscription.watched_obj()), update.response) {
+        (Some(obj), Some(response)) => vec![decode_response(known, obj, response)],
+        _ => Vec::new(),
+    }
+}
+
+/// Decode the response for one object's config entry into a change.
+fn decode_response<T: DeserializeOwned>(
+    known: &mut HashSet<uuid::Uuid>,
+    obj: uuid::Uuid,
+    response: notify_models::NotifyResponse,
+) -> Result<ConfigChange<T>, FetchError> {
+    let etag = response
+        .headers
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("etag"))
+        .map(|(_, value)| value.clone());
+
+    let body = match (response.status, response.body) {
+        (404, _) | (_, None) => None,
+        (_, Some(body)) => Some(body),
+    };
+    if body.is_some() {
+        known.insert(obj);
+    } else {
+        known.remove(&obj);
+    }
+
+    let value = match body {
+        None => None,
+        Some(body) => Some(serde_json::from_value(body).map_err(|_| FetchError {
+            message: format!(
+                "Couldn't parse config for {} into a {}.",
+                obj,
+                std::any::type_name::<T>()
+            ),
+            url: String::from("/notify/v2"),
+            kind: FetchErrorKind::Other,
+        })?),
+    };
+
+    Ok(ConfigChange { obj, value, etag })
+}
+
+mod notify_models {
+    //! Contains the messages exchanged over the notify WebSocket.
+    use std::collections::{BTreeMap, HashMap};
+
+    #[derive(serde::Serialize)]
+    pub struct NotifyRequest {
+        pub method: String,
+        pub uuid: uuid::Uuid,
+        pub request: NotifyHttpRequest,
+    }
+
+    #[derive(serde::Serialize)]
+    pub struct NotifyHttpRequest {
+        pub method: String,
+        pub url: String,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct NotifyUpdate {
+        pub status: u16,
+        /// The object an update to an app subscription is about.
+        pub child: Option<uuid::Uuid>,
+        pub response: Option<NotifyResponse>,
+        /// The full state of an app subscription: every current entry, by object.
+        pub children: Option<BTreeMap<uuid::Uuid, NotifyResponse>>,
+    }
+
+    #[derive(serde::Deserialize)]
+    pub struct NotifyResponse {
+        pub status: u16,
+        pub body: Option<serde_json::Value>,
+        #[serde(default)]
+        pub headers: HashMap<String, String>,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use serde_json::json;
+
+    use super::*;
+
+    const APP: uuid::Uuid = uuid::uuid!("cb40bed5-49ad-4443-a7f5-08c75009da8f");
+    const OBJ_A: uuid::Uuid = uuid::uuid!("2b4a4b3c-2a7e-4c7f-9d35-6b0c7f4a1c01");
+    const OBJ_B: uuid::Uuid = uuid::uuid!("7f0a9e2d-5c1b-4f8e-8a3d-0e4b6c9d2f02");
+
+    #[derive(Debug, PartialEq, serde::Deserialize)]
+    struct Entry {
+        name: String,
+    }
+
+    fn decode(
+        subscription: &Subscription,
+        known: &mut HashSet<uuid::Uuid>,
+        update: serde_json::Value,
+    ) -> Vec<Result<ConfigChange<Entry>, FetchError>> {
+        decode_update(subscription, known, &update.to_string())
+    }
+
+    #[test]
+    fn config_subscription_request() {
+        let session = uuid::Uuid::new_v4();
+        let request = Subscription::Config {
+            app: APP,
+            obj: OBJ_A,
+        }
+        .to_request(session);
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({
+                "method": "WATCH",
+                "uuid": session,
+                "request": {
+                    "method": "GET",
+                    "url": format!("v1/app/{}/object/{}", APP, OBJ_A),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn app_subscription_request() {
+        let session = uuid::Uuid::new_v4();
+        let request = Subscription::App { app: APP }.to_request(session);
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            json!({
+                "method": "SEARCH",
+                "uuid": session,
+                "request": {
+                    "method": "GET",
+                    "url": format!("v1/app/{}/object/", APP),
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn config_update_uses_watched_object_and_any_case_etag() {
+        let mut known = HashSet::new();
+        let changes = decode(
+            &Subscription::Config {
+                app: APP,
+                obj: OBJ_A,
+            },
+            &mut known,
+            json!({
+                "status": 200,
+                "response": {
+                    "status": 200,
+                    "body": { "name": "a" },
+                    "headers": { "ETag": "\"1\"" },
+                },
+            }),
+        );
+
+        assert_eq!(changes.len(), 1);
+        let change = changes[0].as_ref().unwrap();
+        assert_eq!(change.obj, OBJ_A);
+        assert_eq!(
+            change.value,
+            Some(Entry {
+                name: String::from("a")
+            })
+        );
+        assert_eq!(change.etag.as_deref(), Some("\"1\""));
+        assert!(known.contains(&OBJ_A));
+    }
+
+    #[test]
+    fn not_found_response_is_a_deletion() {
+        let mut known = HashSet::from([OBJ_A]);
+        let changes = decode(
+            &Subscription::App { app: APP },
+            &mut known,
+            json!({
+                "status": 200,
+                "child": OBJ_A,
+                "response": { "status": 404 },
+            }),
+        );
+
+        assert_eq!(changes.len(), 1);
+        let change = changes[0].as_ref().unwrap();
+        assert_eq!(change.obj, OBJ_A);
+        assert!(change.is_deleted());
+        assert!(change.etag.is_none());
+        assert!(known.is_empty());
+    }
+
+    #[test]
+    fn rejected_subscription_is_an_error() {
+        let changes = decode(
+            &Subscription::App { app: APP },
+            &mut HashSet::new(),
+            json!({ "status": 403 }),
+        );
+
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].is_err());
+    }
+
+    #[test]
+    fn acknowledgement_gives_no_changes() {
+        let changes = decode(
+            &Subscription::App { app: APP },
+            &mut HashSet::new(),
+            json!({ "status": 201 }),
+        );
+
+        assert!(changes.is_empty());
+    }
+
+    #[test]
+    fn unparseable_entry_is_an_error() {
+        let changes = decode(
+            &Subscription::Config {
+                app: APP,
+                obj: OBJ_A,
+            },
+            &mut HashSet::new(),
+            json!({
+                "status": 200,
+                "response": { "status": 200, "body": { "other": 1 } },
+            }),
+        );
+
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].is_err());
+    }
+
+    #[test]
+    fn full_state_update_lists_entries_and_deletes_missing_ones() {
+        let mut known = HashSet::from([OBJ_A]);
+        let changes = decode(
+            &Subscription::App { app: APP },
+            &mut known,
+            json!({
+                "status": 200,
+                "children": {
+                    OBJ_B.to_string(): {
+                        "status": 200,
+                        "body": { "name": "b" },
+                        "headers": { "etag": "\"2\"" },
+                    },
+                },
+            }),
+        );
+
+        let changes: Vec<_> = changes.into_iter().map(Result::unwrap).collect();
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].obj, OBJ_A);
+        assert!(changes[0].is_deleted());
+        assert_eq!(changes[1].obj, OBJ_B);
+        assert_eq!(
+            changes[1].value,
+            Some(Entry {
+                name: String::from("b")
+            })
+        );
+        assert_eq!(changes[1].etag.as_deref(), Some("\"2\""));
+        assert_eq!(known, HashSet::from([OBJ_B]));
+    }
+
+    #[test]
+    fn invalid_update_is_an_error() {
+        let changes: Vec<Result<ConfigChange<Entry>, FetchError>> = decode_update(
+            &Subscription::App { app: APP },
+            &mut HashSet::new(),
+            "not json",
+        );
+
+        assert_eq!(changes.len(), 1);
+        assert!(changes[0].is_err());
+    }
+}
@@ -3,45 +3,33 @@
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
-use tokio::sync::Mutex;
-
-use crate::error::FetchError;
-use crate::service;
-use crate::service::directory::service_provider::ServiceProvider;
+use crate::error::{FetchError, FetchErrorKind};
+use crate::service::directory::alert::Alert;
+use crate::service::directory::device::{DeviceRecord, DeviceSession, DeviceUuid, SchemaRecord};
+use crate::service::directory::link::Link;
+use crate::service::directory::service_provider::ServiceProviderList;
 use crate::service::request::{FetchOpts, HttpRequestMethod};
-use crate::service::response::{FetchResponse, TokenStruct};
-use crate::service::utils;
+use crate::service::response::FetchResponse;
+use crate::service::service_http::ServiceHttp;
 use crate::service::ServiceType;
+use crate::sparkplug::util::address::Address;
 
 /// The interface for the Factory+ Directory service.
 ///
-/// DirectoryInterface holds a hashmap from service URLS to tokens.
+/// DirectoryInterface looks up the services and devices known to the Directory.
 pub struct DirectoryInterface {
     pub service_type: ServiceType,
-    service_username: String,
-    service_password: String,
-    http_client: Arc<reqwest::Client>,
-    pub service_url: String,
-    tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
+    service_http: Arc<ServiceHttp>,
 }
 
 impl DirectoryInterface {
-    /// Create a new `DirectoryInterface` from a username, password, HTTP client, and directory url.
-    pub fn from(
-        service_username: String,
-        service_password: String,
-        http_client: Arc<reqwest::Client>,
-        service_url: String,
-        tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
-    ) -> Self {
+    /// Create a new `DirectoryInterface` which makes its requests through the given service HTTP layer.
+    pub fn from(service_http: Arc<ServiceHttp>) -> Self {
         DirectoryInterface {
             service_type: ServiceType::Directory,
-            service_username,
-            service_password,
-            http_client,
-            service_url,
-            tokens,
+            service_http,
         }
     }
 
@@ -50,8 +38,10 @@ impl DirectoryInterface {
         &self,
         service: ServiceType,
     ) -> Result<Option<Vec<String>>, FetchError> {
+        let target_url = format!("/v1/service/{}", service.to_uuid());
+
         let fetch_opts = FetchOpts {
-            url: format!("{}/v1/service/{}", self.service_url, service.to_uuid()),
+            url: target_url.clone(),
             service: ServiceType::Directory,
             method: HttpRequestMethod::GET,
             headers: reqwest::header::HeaderMap::new(),
@@ -63,26 +53,12 @@ impl DirectoryInterface {
 
         match response.status {
             http::status::StatusCode::OK => {
-                let service_providers_result: Result<Vec<ServiceProvider>, serde_json::Error> =
-                    serde_json::from_str(&response.content);
-                match service_providers_result {
-                    Ok(service_providers_vec) => Ok(Some(
-                        service_providers_vec
-                            .iter()
-                            .filter_map(|x| {
-                                x.url.as_ref().map(|url| {
-                                    let mut url = url.clone();
-                                    if url.ends_with('/') {
-                                        url = url.strip_suffix('/').unwrap().to_string();
-                                    }
-                                    url
-                                })
-                            })
-                            .collect(),
-                    )),
+                match service_provider::urls_from_response(&response.content) {
+                    Ok(urls) => Ok(Some(urls)),
                     Err(_) => Err(FetchError {
                         message: String::from("Couldn't decode service response."),
-                        url: self.service_url.clone(),
+                        url: target_url,
+                        kind: FetchErrorKind::Other,
                     }),
                 }
             }
@@ -90,103 +66,440 @@ impl DirectoryInterface {
         }
     }
 
-    /// Register a service url against a service name in the directory.
-    pub async fn register_service_url(
-        &mut self,
-        service_name: String,
-        url: String,
+    /// Gets every provider of a service: the device advertising it, its URL, and whether that
+    /// device is currently online according to the Directory.
+    ///
+    /// The online state is read with one further Directory request per provider. If that request
+    /// fails, the provider is still listed with its online state left as None.
+    ///
+    /// Returns None if the Directory has no record of the service.
+    pub async fn service_providers(
+        &self,
+        service: ServiceType,
+    ) -> Result<Option<ServiceProviderList>, FetchError> {
+        let target_url = format!("/v1/service/{}", service.to_uuid());
+        let response = self.get(&target_url).await?;
+
+        let mut provider_list = match response.status {
+            http::status::StatusCode::OK => {
+                match service_provider::providers_from_response(&response.content) {
+                    Ok(providers) => ServiceProviderList::from(providers),
+                    Err(_) => {
+                        return Err(FetchError {
+                            message: String::from("Couldn't decode service response."),
+                            url: target_url,
+                            kind: FetchErrorKind::Other,
+                        })
+                    }
+                }
+            }
+            _ => return Ok(None),
+        };
+
+        for provider in provider_list.list.iter_mut() {
+            if let Some(device) = provider.device {
+                provider.online = self
+                    .get_device(device)
+                    .await
+                    .ok()
+                    .flatten()
+                    .map(|record| record.online);
+            }
+        }
+
+        Ok(Some(provider_list))
+    }
+
+    /// Advertise that `url` provides a service. If `device` is given, the advertisement is made
+    /// on behalf of that device, otherwise on behalf of the principal the client is logged in as.
+    pub async fn advertise_service(
+        &self,
+        service: uuid::Uuid,
+        url: &str,
+        device: Option<uuid::Uuid>,
     ) -> Result<FetchResponse, FetchError> {
+        let target_url = format!("/v1/service/{}/advertisement", service);
+        let body = serde_json::to_string(&Advertisement {
+            url: String::from(url),
+            device,
+        })
+        .map_err(|_| FetchError {
+            message: String::from("Couldn't serialise service advertisement."),
+            url: target_url.clone(),
+            kind: FetchErrorKind::Other,
+        })?;
+
         let opts = FetchOpts {
-            url: format!(
-                "{}/v1/service/{}/advertisement",
-                self.service_url, service_name
-            ),
+            url: target_url,
             service: ServiceType::Directory,
             method: HttpRequestMethod::PUT,
             headers: reqwest::header::HeaderMap::new(),
             query: None,
-            body: Some(format!("{{\"url\": \"{}\"}}", url)),
+            body: Some(body),
         };
 
         self.fetch(opts).await
     }
-    async fn fetch(&self, fetch_opts: FetchOpts) -> Result<FetchResponse, FetchError> {
-        let current_directory_token = self.get_directory_token().await?;
-
-        let headers =
-            utils::check_correct_headers(&fetch_opts.headers, &fetch_opts.body, &fetch_opts.url)?;
-
-        if let Ok(request) = match (fetch_opts.query, fetch_opts.body) {
-            (None, None) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers),
-            (Some(query), None) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers)
-                .query(&query),
-            (None, Some(body)) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers)
-                .body(body),
-            (Some(query), Some(body)) => self
-                .http_client
-                .request(fetch_opts.method.to_method(), fetch_opts.url.clone())
-                .headers(headers)
-                .query(&query)
-                .body(body),
-        }
-        .bearer_auth(current_directory_token.token)
-        .build()
-        {
-            match self.http_client.execute(request).await {
-                Ok(response) => {
-                    let response_status = response.status();
-
-                    if let Ok(response_body) = response.text().await {
-                        Ok(FetchResponse {
-                            status: response_status,
-                            content: response_body,
-                        })
-                    } else {
-                        Err(FetchError {
-                            message: String::from("Couldn't decode response body."),
-                            url: fetch_opts.url,
-                        })
-                    }
-                }
-                _ => Err(FetchError {
-                    message: String::from("Couldn't make request."),
-                    url: fetch_opts.url,
+
+    /// Withdraw an advertisement made with `advertise_service`.
+    pub async fn withdraw_advertisement(
+        &self,
+        service: uuid::Uuid,
+    ) -> Result<FetchResponse, FetchError> {
+        let opts = FetchOpts {
+            url: format!("/v1/service/{}/advertisement", service),
+            service: ServiceType::Directory,
+            method: HttpRequestMethod::DELETE,
+            headers: reqwest::header::HeaderMap::new(),
+            query: None,
+            body: None,
+        };
+
+        self.fetch(opts).await
+    }
+
+    /// Advertise a service now and again every `interval` in a background task, so that the
+    /// advertisement is restored if the Directory loses it. The task runs until the returned
+    /// handle is dropped or stopped. The outcome of each attempt is available from the handle.
+    ///
+    /// This must be called from within a Tokio runtime.
+    pub fn keep_advertised(
+        &self,
+        service: uuid::Uuid,
+        url: &str,
+        device: Option<uuid::Uuid>,
+        interval: Duration,
+    ) -> AdvertisementHandle {
+        let directory = DirectoryInterface::from(Arc::clone(&self.service_http));
+        let url = String::from(url);
+        let (result_sender, result) = tokio::sync::watch::channel(None);
+
+        let task = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let outcome = match directory.advertise_service(service, &url, device).await {
+                    Ok(res) if res.status.is_success() => Ok(()),
+                    Ok(res) => Err(FetchError {
+                        message: format!("{}: Couldn't advertise service {}.", res.status, service),
+                        url: format!("/v1/service/{}/advertisement", service),
+                        kind: FetchErrorKind::Other,
+                    }),
+                    Err(e) => Err(e),
+                };
+                // The handle holds a receiver for as long as this task runs.
+                let _ = result_sender.send(Some(outcome));
+            }
+        });
+
+        AdvertisementHandle {
+            service,
+            service_http: Arc::clone(&self.service_http),
+            task,
+            result,
+        }
+    }
+
+    /// Register a service url against a service in the directory.
+    #[deprecated(note = "use `advertise_service`, which takes the service UUID")]
+    pub async fn register_service_url(
+        &self,
+        service_name: String,
+        url: String,
+    ) -> Result<FetchResponse, FetchError> {
+        match uuid::Uuid::parse_str(&service_name) {
+            Ok(service) => self.advertise_service(service, &url, None).await,
+            Err(_) => Err(FetchError {
+                message: format!("{} is not a service UUID.", service_name),
+                url: format!("/v1/service/{}/advertisement", service_name),
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// List the UUIDs of every device known to the Directory.
+    pub async fn list_devices(&self) -> Result<Vec<uuid::Uuid>, FetchError> {
+        let url = String::from("/v1/device");
+        let res = self.get(&url).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                serde_json::from_str(&res.content).map_err(|_| FetchError {
+                    message: String::from("Failed to parse a UUID from response."),
+                    url,
+                    kind: FetchErrorKind::Other,
+                })
+            }
+            _ => Err(FetchError {
+                message: format!("{}: Can't list devices.", res.status),
+                url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// Get the Directory's record of a device, if it knows of the device.
+    pub async fn get_device(&self, device: uuid::Uuid) -> Result<Option<DeviceRecord>, FetchError> {
+        let url = format!("/v1/device/{}", device);
+        let res = self.get(&url).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                serde_json::from_str(&res.content)
+                    .map(Some)
+                    .map_err(|_| FetchError {
+                        message: String::from("Couldn't parse response into a device record."),
+                        url,
+                        kind: FetchErrorKind::Other,
+                    })
+            }
+            http::status::StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(FetchError {
+                message: format!("{}: Can't get device {}.", res.status, device),
+                url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// Find the UUID of the device at a Sparkplug address.
+    pub async fn find_device_uuid(
+        &self,
+        address: &Address,
+    ) -> Result<Option<uuid::Uuid>, FetchError> {
+        let url = format!("/v1/address/{}", address);
+        let res = self.get(&url).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => serde_json::from_str::<DeviceUuid>(&res.content)
+                .map(|found| Some(found.uuid))
+                .map_err(|_| FetchError {
+                    message: String::from("Couldn't parse response into a device UUID."),
+                    url,
+                    kind: FetchErrorKind::Other,
                 }),
+            http::status::StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(FetchError {
+                message: format!("{}: Can't look up device at {}.", res.status, address),
+                url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// Find the Directory's record of the device at a Sparkplug address.
+    pub async fn find_device(&self, address: &Address) -> Result<Option<DeviceRecord>, FetchError> {
+        match self.find_device_uuid(address).await? {
+            Some(device) => self.get_device(device).await,
+            None => Ok(None),
+        }
+    }
+
+    /// Get a device's session history: each time it came online (its birth) and, unless it is
+    /// still online, when it went offline (its death).
+    ///
+    /// Returns None if the Directory doesn't know of the device.
+    pub async fn device_sessions(
+        &self,
+        device: uuid::Uuid,
+    ) -> Result<Option<Vec<DeviceSession>>, FetchError> {
+        self.get_history(&format!("/v1/device/{}/history/session", device), "session")
+            .await
+    }
+
+    /// Get a device's schema history: each schema and schema instance it has published, and
+    /// when.
+    ///
+    /// Returns None if the Directory doesn't know of the device.
+    pub async fn device_schemas(
+        &self,
+        device: uuid::Uuid,
+    ) -> Result<Option<Vec<SchemaRecord>>, FetchError> {
+        self.get_history(&format!("/v1/device/{}/history/schema", device), "schema")
+            .await
+    }
+
+    /// List the active alerts, optionally only those of an alert type and/or raised by a device.
+    pub async fn list_alerts(
+        &self,
+        alert_type: Option<uuid::Uuid>,
+        device: Option<uuid::Uuid>,
+    ) -> Result<Vec<Alert>, FetchError> {
+        let query = [("type", alert_type), ("device", device)]
+            .into_iter()
+            .filter_map(|(key, maybe_uuid)| {
+                maybe_uuid.map(|uuid| (String::from(key), uuid.to_string()))
+            })
+            .collect();
+
+        self.get_list("/v1/alert", query, "alerts").await
+    }
+
+    /// Get an alert, if the Directory knows of it.
+    pub async fn get_alert(&self, alert: uuid::Uuid) -> Result<Option<Alert>, FetchError> {
+        let url = format!("/v1/alert/{}", alert);
+        let res = self.get(&url).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                serde_json::from_str(&res.content)
+                    .map(Some)
+                    .map_err(|_| FetchError {
+                        message: String::from("Couldn't parse response into an alert."),
+                        url,
+                        kind: FetchErrorKind::Other,
+                    })
             }
-        } else {
-            Err(FetchError {
-                message: String::from("Couldn't build a request to fetch."),
-                url: fetch_opts.url,
+            http::status::StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(FetchError {
+                message: format!("{}: Can't get alert {}.", res.status, alert),
+                url,
+                kind: FetchErrorKind::Other,
+            }),
+        }
+    }
+
+    /// List the links between devices, optionally only those of a relation and/or from a device.
+    pub async fn list_links(
+        &self,
+        relation: Option<uuid::Uuid>,
+        device: Option<uuid::Uuid>,
+    ) -> Result<Vec<Link>, FetchError> {
+        let query = [("relation", relation), ("device", device)]
+            .into_iter()
+            .filter_map(|(key, maybe_uuid)| {
+                maybe_uuid.map(|uuid| (String::from(key), uuid.to_string()))
             })
+            .collect();
+
+        self.get_list("/v1/link", query, "links").await
+    }
+
+    async fn get_list<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+        query: HashMap<String, String>,
+        list_kind: &str,
+    ) -> Result<Vec<T>, FetchError> {
+        let opts = FetchOpts {
+            url: String::from(url),
+            service: ServiceType::Directory,
+            method: HttpRequestMethod::GET,
+            headers: Default::default(),
+            query: Some(query),
+            body: None,
+        };
+
+        let res = self.fetch(opts).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                serde_json::from_str(&res.content).map_err(|_| FetchError {
+                    message: format!("Couldn't parse response into a list of {}.", list_kind),
+                    url: String::from(url),
+                    kind: FetchErrorKind::Other,
+                })
+            }
+            _ => Err(FetchError {
+                message: format!("{}: Can't list {}.", res.status, list_kind),
+                url: String::from(url),
+                kind: FetchErrorKind::Other,
+            }),
         }
     }
 
-    async fn get_directory_token(&self) -> Result<TokenStruct, FetchError> {
-        let mut locked_tokens = self.tokens.lock().await;
-        // If we find a local token, return it. Otherwise, we request a new one.
-        if let Some(token) = locked_tokens.get(&ServiceType::Directory) {
-            Ok(token.clone())
-        } else {
-            let new_token = service::fetch_util::get_new_token(
-                Arc::clone(&self.http_client),
-                self.service_url.clone(),
-                &self.service_username,
-                &self.service_password,
-            )
-            .await?;
-            locked_tokens.insert(ServiceType::Directory, new_token.clone());
-            Ok(new_token)
+    async fn get_history<T: serde::de::DeserializeOwned>(
+        &self,
+        url: &str,
+        history_kind: &str,
+    ) -> Result<Option<Vec<T>>, FetchError> {
+        let res = self.get(url).await?;
+
+        match res.status {
+            http::status::StatusCode::OK => {
+                serde_json::from_str(&res.content)
+                    .map(Some)
+                    .map_err(|_| FetchError {
+                        message: format!("Couldn't parse response into {} history.", history_kind),
+                        url: String::from(url),
+                        kind: FetchErrorKind::Other,
+                    })
+            }
+            http::status::StatusCode::NOT_FOUND => Ok(None),
+            _ => Err(FetchError {
+                message: format!("{}: Can't get {} history.", res.status, history_kind),
+                url: String::from(url),
+                kind: FetchErrorKind::Other,
+            }),
         }
     }
+
+    async fn get(&self, url: &str) -> Result<FetchResponse, FetchError> {
+        let opts = FetchOpts {
+            url: String::from(url),
+            service: ServiceType::Directory,
+            method: HttpRequestMethod::GET,
+            headers: Default::default(),
+            query: None,
+            body: None,
+        };
+
+        self.fetch(opts).await
+    }
+
+    async fn fetch(&self, fetch_opts: FetchOpts) -> Result<FetchResponse, FetchError> {
+        self.service_http.fetch(fetch_opts).await
+    }
+}
+
+/// The body of a service advertisement.
+#[derive(serde::Serialize)]
+struct Advertisement {
+    url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    device: Option<uuid::Uuid>,
+}
+
+/// A handle to a background task started by `DirectoryInterface::keep_advertised`. Dropping the
+/// handle stops the task but leaves the advertisement in place.
+pub struct AdvertisementHandle {
+    service: uuid::Uuid,
+    service_http: Arc<ServiceHttp>,
+    task: tokio::task::JoinHandle<()>,
+    result: tokio::sync::watch::Receiver<Option<Result<(), FetchError>>>,
+}
+
+impl AdvertisementHandle {
+    /// The outcome of the most recent attempt to advertise the service, or None if the first
+    /// attempt hasn't finished yet.
+    pub fn last_result(&self) -> Option<Result<(), FetchError>> {
+        self.result.borrow().clone()
+    }
+
+    /// Get a receiver which is updated with the outcome of every attempt to advertise the
+    /// service, for waiting on failures rather than polling `last_result`.
+    pub fn results(&self) -> tokio::sync::watch::Receiver<Option<Result<(), FetchError>>> {
+        self.result.clone()
+    }
+
+    /// Stop re-advertising the service, leaving the advertisement in place.
+    pub fn stop(self) {
+        self.task.abort();
+    }
+
+    /// Stop re-advertising the service and withdraw its advertisement.
+    pub async fn withdraw(self) -> Result<FetchResponse, FetchError> {
+        self.task.abort();
+        DirectoryInterface::from(Arc::clone(&self.service_http))
+            .withdraw_advertisement(self.service)
+            .await
+    }
+}
+
+impl Drop for AdvertisementHandle {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
 }
 
 pub mod service_provider {
@@ -194,10 +507,14 @@ pub mod service_provider {
 
     use serde::Deserialize;
 
-    #[derive(Deserialize)]
+    #[derive(Debug, Clone, Deserialize)]
     pub struct ServiceProvider {
         pub device: Option<uuid::Uuid>,
         pub url: Option<String>,
+        /// Whether the advertising device is online according to the Directory. This is None if
+        /// the provider has no device, or the Directory has no record of it or couldn't be asked.
+        #[serde(skip)]
+        pub online: Option<bool>,
     }
 
     impl ServiceProvider {
@@ -205,10 +522,12 @@ pub mod service_provider {
             ServiceProvider {
                 device: Some(device),
                 url: Some(url),
+                online: None,
             }
         }
     }
 
+    #[derive(Debug, Clone)]
     pub struct ServiceProviderList {
         pub list: Vec<ServiceProvider>,
     }
@@ -217,5 +536,158 @@ pub mod service_provider {
         pub fn from(list: Vec<ServiceProvider>) -> Self {
             ServiceProviderList { list }
         }
+
+        /// The URLs of the providers, in order. Providers which don't advertise a URL are skipped.
+        pub fn urls(&self) -> Vec<String> {
+            self.list
+                .iter()
+                .filter_map(|provider| provider.url.clone())
+                .collect()
+        }
+
+        /// The providers whose device is known to be online.
+        pub fn online(&self) -> impl Iterator<Item = &ServiceProvider> {
+            self.list
+                .iter()
+                .filter(|provider| provider.online == Some(true))
+        }
+
+        /// The provider advertised by a device, if there is one.
+        pub fn by_device(&self, device: uuid::Uuid) -> Option<&ServiceProvider> {
+            self.list
+                .iter()
+                .find(|provider| provider.device == Some(device))
+        }
+    }
+
+    /// Decode a Directory service lookup response into the providers, with trailing slashes
+    /// stripped from their urls.
+    pub fn providers_from_response(
+        content: &str,
+    ) -> Result<Vec<ServiceProvider>, serde_json::Error> {
+        let service_providers: Vec<ServiceProvider> = serde_json::from_str(content)?;
+        Ok(service_providers
+            .into_iter()
+            .map(|provider| ServiceProvider {
+                url: provider
+                    .url
+                    .map(|url| url.strip_suffix('/').map(String::from).unwrap_or(url)),
+                ..provider
+            })
+            .collect())
+    }
+
+    /// Decode a Directory service lookup response into the advertised urls, without trailing
+    /// slashes. Providers which don't advertise a url are skipped.
+    pub fn urls_from_response(content: &str) -> Result<Vec<String>, serde_json::Error> {
+        Ok(ServiceProviderList::from(providers_from_response(content)?).urls())
+    }
+}
+
+pub mod device {
+    //! Contains structs and implementations for representations of devices known to the Directory.
+
+    use serde::Deserialize;
+
+    use crate::sparkplug::util::address::{Address, AddressType};
+
+    /// The Directory's record of a device.
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct DeviceRecord {
+        pub uuid: uuid::Uuid,
+        /// Whether the device is currently online in Sparkplug.
+        #[serde(default)]
+        pub online: bool,
+        pub group_id: String,
+        pub node_id: String,
+        /// The device name, if this is a Sparkplug device rather than a node.
+        pub device_id: Option<String>,
+        /// The UUID of the schema the device's top-level metrics follow.
+        pub top_schema: Option<uuid::Uuid>,
+        /// When the device last came online or went offline, as reported by the Directory.
+        pub last_change: Option<String>,
+    }
+
+    impl DeviceRecord {
+        /// The Sparkplug address of the device.
+        pub fn address(&self) -> Address {
+            Address {
+                group: self.group_id.clone(),
+                node: self.node_id.clone(),
+                address_type: match &self.device_id {
+                    Some(device_id) => AddressType::Device(device_id.clone()),
+                    None => AddressType::Node,
+                },
+            }
+        }
+    }
+
+    /// One online session of a device, from its birth to its death. Times are as reported by the
+    /// Directory.
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct DeviceSession {
+        /// When the device's birth was seen.
+        pub start: String,
+        /// When the device's death was seen, or None if the session is still live.
+        pub finish: Option<String>,
+    }
+
+    /// A period during which a device published metrics under a schema.
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct SchemaRecord {
+        /// The UUID of the schema.
+        pub schema: uuid::Uuid,
+        /// The UUID of the schema instance within the device, if the device reported one.
+        pub instance: Option<uuid::Uuid>,
+        /// When the device started publishing under the schema.
+        pub start: String,
+        /// When the device stopped publishing under the schema, or None if it still does.
+        pub finish: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub(crate) struct DeviceUuid {
+        pub uuid: uuid::Uuid,
+    }
+}
+
+pub mod alert {
+    //! Contains structs and implementations for representations of alerts raised by devices.
+
+    use serde::Deserialize;
+
+    /// An active alert raised by a device.
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct Alert {
+        pub uuid: uuid::Uuid,
+        /// The UUID of the alert type.
+        #[serde(rename = "type")]
+        pub alert_type: uuid::Uuid,
+        /// The UUID of the device which raised the alert.
+        pub device: uuid::Uuid,
+        /// The metric the alert was raised from, if any.
+        pub metric: Option<String>,
+        /// When the alert was raised, as reported by the Directory.
+        pub since: Option<String>,
+    }
+}
+
+pub mod link {
+    //! Contains structs and implementations for representations of links between devices.
+
+    use serde::Deserialize;
+
+    /// A link from a device to another object, such as the device it is connected to.
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct Link {
+        pub uuid: uuid::Uuid,
+        /// The UUID of the device the link is from.
+        pub device: uuid::Uuid,
+        /// The UUID of the relation the link expresses.
+        pub relation: uuid::Uuid,
+        /// The UUID of the object the link points to.
+        pub target: uuid::Uuid,
+        /// The metric the link was published in, if any.
+        pub metric: Option<String>,
     }
 }
@@ -2,22 +2,56 @@
 //! Discovery service.
 
 use std::collections::HashMap;
-use std::sync::Arc;
-
-use tokio::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use crate::error::FetchError;
 use crate::service::directory::DirectoryInterface;
-use crate::service::response::TokenStruct;
 use crate::service::ServiceType;
 
+/// How long URLs found in the Directory are cached for, unless configured otherwise.
+pub const DEFAULT_DISCOVERY_TTL: Duration = Duration::from_secs(300);
+
+/// How long a provider which failed a request is passed over for while other providers are
+/// available.
+pub const UNHEALTHY_BACKOFF: Duration = Duration::from_secs(30);
+
+/// How to choose between the providers of a service when more than one is known.
+///
+/// Whichever policy is used, providers which recently failed a request are only tried after the
+/// others.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ProviderSelection {
+    /// Always prefer the first provider, moving to the next only when it fails.
+    #[default]
+    FirstHealthy,
+    /// Start each request at the provider after the one the last request started at.
+    RoundRobin,
+    /// Start each request at a randomly chosen provider.
+    Random,
+}
+
 /// The interface for the Factory+ Discovery service.
 ///
-/// DiscoveryInterface holds a hashmap from service UUIDs to service URLs. These can be queried
-/// locally and can use the Directory service if not found locally.
+/// DiscoveryInterface holds a hashmap from services to service URLs. These can be queried
+/// locally and can use the Directory service if not found locally. Any service can be held,
+/// including services outside Factory+ core given as `ServiceType::Other`.
+///
+/// URLs found in the Directory are cached separately from the preconfigured URLs. Cached URLs
+/// expire after a TTL and can be invalidated when a request to them fails, while preconfigured
+/// URLs are always used as given.
 pub struct DiscoveryInterface {
     pub urls: HashMap<ServiceType, Vec<String>>,
-    tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
+    cache: HashMap<ServiceType, CachedServiceUrls>,
+    ttl: Duration,
+    selection: HashMap<ServiceType, ProviderSelection>,
+    next_provider: HashMap<ServiceType, usize>,
+    unhealthy: HashMap<String, Instant>,
+}
+
+/// URLs found in the Directory for a service, and when they were found.
+struct CachedServiceUrls {
+    urls: Vec<String>,
+    fetched_at: Instant,
 }
 
 impl DiscoveryInterface {
@@ -32,7 +66,6 @@ impl DiscoveryInterface {
         directory_url: Option<String>,
         mqtt_url: Option<String>,
         cmd_esc_url: Option<String>,
-        tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
     ) -> Self {
         let mut urls_map: HashMap<ServiceType, Vec<String>> = HashMap::new();
 
@@ -56,8 +89,62 @@ impl DiscoveryInterface {
         .for_each(insert_maybe_url);
         DiscoveryInterface {
             urls: urls_map,
-            tokens,
+            cache: HashMap::new(),
+            ttl: DEFAULT_DISCOVERY_TTL,
+            selection: HashMap::new(),
+            next_provider: HashMap::new(),
+            unhealthy: HashMap::new(),
+        }
+    }
+
+    /// Set how long URLs found in the Directory are cached for.
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
+    /// Set how to choose between the providers of a service. Services use
+    /// `ProviderSelection::FirstHealthy` unless configured otherwise.
+    pub fn set_provider_selection(&mut self, service: ServiceType, selection: ProviderSelection) {
+        self.selection.insert(service, selection);
+    }
+
+    /// Puts the URLs of a service's providers in the order they should be tried, according to
+    /// the service's provider selection. Providers which recently failed are moved to the end.
+    pub fn order_providers(&mut self, service: &ServiceType, urls: Vec<String>) -> Vec<String> {
+        let mut urls = urls;
+        if urls.len() > 1 {
+            let start = match self.selection.get(service).copied().unwrap_or_default() {
+                ProviderSelection::FirstHealthy => 0,
+                ProviderSelection::RoundRobin => {
+                    let next = self.next_provider.entry(service.clone()).or_default();
+                    let start = *next % urls.len();
+                    *next = start + 1;
+                    start
+                }
+                ProviderSelection::Random => {
+                    (uuid::Uuid::new_v4().as_u128() % urls.len() as u128) as usize
+                }
+            };
+            urls.rotate_left(start);
         }
+
+        self.unhealthy
+            .retain(|_, failed_at| failed_at.elapsed() < UNHEALTHY_BACKOFF);
+        // A stable sort keeps the selected order within the healthy and unhealthy providers.
+        urls.sort_by_key(|url| self.unhealthy.contains_key(url));
+        urls
+    }
+
+    /// Records that a request to a provider failed, so that it is tried after the others for a
+    /// while.
+    pub fn mark_unhealthy(&mut self, service_url: &str) {
+        self.unhealthy
+            .insert(String::from(service_url), Instant::now());
+    }
+
+    /// Records that a request to a provider succeeded.
+    pub fn mark_healthy(&mut self, service_url: &str) {
+        self.unhealthy.remove(service_url);
     }
 
     /// Inserts a (uuid, url) pair into the urls map. This overwrites the current vector or urls.
@@ -65,7 +152,7 @@ impl DiscoveryInterface {
     ///
     /// If the key was already present in the map, the old value is returned.
     /// Otherwise, None is returned.
-    pub(crate) fn set_service_url(
+    pub fn set_service_url(
         &mut self,
         service: ServiceType,
         service_url: String,
@@ -76,20 +163,55 @@ impl DiscoveryInterface {
     /// Inserts a (uuid, url) pair into the urls map. This adds to the current vector value assigned
     /// to the url key if the key already exists.
     /// This requires a mutable reference to the DiscoveryInterface.
-    pub(crate) fn add_service_url(&mut self, service: ServiceType, service_url: String) {
+    pub fn add_service_url(&mut self, service: ServiceType, service_url: String) {
         self.urls.entry(service).or_default().push(service_url);
     }
 
+    /// Gets the URLs for a service that are known without asking the Directory: the
+    /// preconfigured URLs, or else any cached Directory URLs which haven't expired.
+    pub fn known_service_urls(&self, service: &ServiceType) -> Option<Vec<String>> {
+        if let Some(urls) = self.urls.get(service).filter(|urls| !urls.is_empty()) {
+            return Some(urls.clone());
+        }
+
+        self.cache
+            .get(service)
+            .filter(|cached| cached.fetched_at.elapsed() < self.ttl && !cached.urls.is_empty())
+            .map(|cached| cached.urls.clone())
+    }
+
+    /// Caches the URLs found in the Directory for a service, replacing any already cached.
+    pub fn cache_service_urls(&mut self, service: ServiceType, service_urls: Vec<String>) {
+        self.cache.insert(
+            service,
+            CachedServiceUrls {
+                urls: service_urls,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Removes a URL from the cached Directory URLs for a service, so that the next lookup uses
+    /// another cached URL or asks the Directory again. Preconfigured URLs are not affected.
+    pub fn invalidate_service_url(&mut self, service: &ServiceType, service_url: &str) {
+        if let Some(cached) = self.cache.get_mut(service) {
+            cached.urls.retain(|url| url != service_url);
+            if cached.urls.is_empty() {
+                self.cache.remove(service);
+            }
+        }
+    }
+
     /// Gets all known URLS that point to a service with the given UUID.
-    /// The preconfigured URLS are queried first. If the service is not found, the Directory service
-    /// is queried.
+    /// The preconfigured and cached URLS are queried first. If the service is not found, the
+    /// Directory service is queried.
     pub async fn get_service_urls(
         &self,
         service: ServiceType,
         directory_interface: &DirectoryInterface,
     ) -> Result<Option<Vec<String>>, FetchError> {
-        if let Some(url) = self.urls.get(&service).cloned() {
-            Ok(Some(url))
+        if let Some(urls) = self.known_service_urls(&service) {
+            Ok(Some(urls))
         } else {
             self.find_service_urls(service, directory_interface).await
         }
@@ -104,3 +226,135 @@ impl DiscoveryInterface {
         directory_interface.service_urls(service).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(urls: &[&str]) -> Vec<String> {
+        urls.iter().map(|url| String::from(*url)).collect()
+    }
+
+    fn discovery() -> DiscoveryInterface {
+        DiscoveryInterface::from(None, None, None, None, None)
+    }
+
+    #[test]
+    fn first_healthy_keeps_the_given_order() {
+        let mut discovery = discovery();
+        let providers = urls(&["http://a", "http://b", "http://c"]);
+
+        for _ in 0..3 {
+            assert_eq!(
+                discovery.order_providers(&ServiceType::ConfigDb, providers.clone()),
+                providers
+            );
+        }
+    }
+
+    #[test]
+    fn round_robin_starts_at_the_next_provider_each_time() {
+        let mut discovery = discovery();
+        discovery.set_provider_selection(ServiceType::ConfigDb, ProviderSelection::RoundRobin);
+        let providers = urls(&["http://a", "http://b", "http://c"]);
+
+        let starts: Vec<String> = (0..4)
+            .map(|_| {
+                discovery.order_providers(&ServiceType::ConfigDb, providers.clone())[0].clone()
+            })
+            .collect();
+        assert_eq!(
+            starts,
+            urls(&["http://a", "http://b", "http://c", "http://a"])
+        );
+
+        // Other services keep their own position.
+        assert_eq!(
+            discovery.order_providers(&ServiceType::Directory, providers.clone()),
+            providers
+        );
+    }
+
+    #[test]
+    fn random_keeps_every_provider() {
+        let mut discovery = discovery();
+        discovery.set_provider_selection(ServiceType::ConfigDb, ProviderSelection::Random);
+        let providers = urls(&["http://a", "http://b", "http://c"]);
+
+        let mut ordered = discovery.order_providers(&ServiceType::ConfigDb, providers.clone());
+        ordered.sort();
+        assert_eq!(ordered, providers);
+    }
+
+    #[test]
+    fn unhealthy_providers_are_tried_last() {
+        let mut discovery = discovery();
+        discovery.mark_unhealthy("http://a");
+
+        assert_eq!(
+            discovery.order_providers(
+                &ServiceType::ConfigDb,
+                urls(&["http://a", "http://b", "http://c"])
+            ),
+            urls(&["http://b", "http://c", "http://a"])
+        );
+    }
+
+    #[test]
+    fn healthy_providers_are_no_longer_passed_over() {
+        let mut discovery = discovery();
+        discovery.mark_unhealthy("http://a");
+        discovery.mark_healthy("http://a");
+
+        assert_eq!(
+            discovery.order_providers(&ServiceType::ConfigDb, urls(&["http://a", "http://b"])),
+            urls(&["http://a", "http://b"])
+        );
+    }
+
+    #[test]
+    fn unhealthy_backoff_expires() {
+        let mut discovery = discovery();
+        discovery.unhealthy.insert(
+            String::from("http://a"),
+            Instant::now() - UNHEALTHY_BACKOFF - Duration::from_secs(1),
+        );
+
+        assert_eq!(
+            discovery.order_providers(&ServiceType::ConfigDb, urls(&["http://a", "http://b"])),
+            urls(&["http://a", "http://b"])
+        );
+        assert!(discovery.unhealthy.is_empty());
+    }
+
+    #[test]
+    fn preconfigured_urls_are_preferred_to_cached_ones() {
+        let mut discovery = discovery();
+        discovery.cache_service_urls(ServiceType::ConfigDb, urls(&["http://cached"]));
+        assert_eq!(
+            discovery.known_service_urls(&ServiceType::ConfigDb),
+            Some(urls(&["http://cached"]))
+        );
+
+        discovery.set_service_url(ServiceType::ConfigDb, String::from("http://configured"));
+        assert_eq!(
+            discovery.known_service_urls(&ServiceType::ConfigDb),
+            Some(urls(&["http://configured"]))
+        );
+    }
+
+    #[test]
+    fn cached_urls_expire_and_can_be_invalidated() {
+        let mut discovery = discovery();
+        discovery.cache_service_urls(ServiceType::ConfigDb, urls(&["http://a", "http://b"]));
+
+        discovery.invalidate_service_url(&ServiceType::ConfigDb, "http://a");
+        assert_eq!(
+            discovery.known_service_urls(&ServiceType::ConfigDb),
+            Some(urls(&["http://b"]))
+        );
+
+        discovery.set_ttl(Duration::ZERO);
+        assert_eq!(discovery.known_service_urls(&ServiceType::ConfigDb), None);
+    }
+}
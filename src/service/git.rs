@@ -0,0 +1,177 @@
+//! This module provides an implementation of GitInterface for interacting with the Factory+ Git
+//! service.
+//!
+//! Repositories and repository groups are ConfigDB objects of class `uuids::types::GIT_REPO` and
+//! `uuids::types::GIT_REPO_GROUP`. Their path on the Git service is held in their
+//! `uuids::app::GIT_REPO_CONFIG` config entry, with a repository's path under its group's path.
+
+use std::sync::Arc;
+
+use crate::error::{FetchError, FetchErrorKind};
+use crate::service::configdb::ConfigDbInterface;
+use crate::service::git::git_models::{GitRepo, GitRepoConfig};
+use crate::service::response::TokenStruct;
+use crate::service::service_http::ServiceHttp;
+use crate::service::ServiceType;
+use crate::uuids;
+
+/// The interface for the Factory+ Git service.
+pub struct GitInterface {
+    pub service_type: ServiceType,
+    service_http: Arc<ServiceHttp>,
+    config_db: ConfigDbInterface,
+}
+
+impl GitInterface {
+    /// Create a new `GitInterface` which makes its requests through the given service HTTP layer.
+    pub fn from(service_http: Arc<ServiceHttp>) -> Self {
+        GitInterface {
+            service_type: ServiceType::Git,
+            config_db: ConfigDbInterface::from(Arc::clone(&service_http)),
+            service_http,
+        }
+    }
+
+    /// List the repositories known to the ConfigDB. Repositories without a config entry are
+    /// skipped.
+    pub async fn list_repos(&self) -> Result<Vec<GitRepo>, FetchError> {
+        self.list_with_config(uuids::types::GIT_REPO).await
+    }
+
+    /// List the repository groups known to the ConfigDB. Groups without a config entry are
+    /// skipped.
+    pub async fn list_groups(&self) -> Result<Vec<GitRepo>, FetchError> {
+        self.list_with_config(uuids::types::GIT_REPO_GROUP).await
+    }
+
+    /// Get a repository or group with its config entry, if it has one.
+    pub async fn get_repo(&self, repo: uuid::Uuid) -> Result<Option<GitRepo>, FetchError> {
+        Ok(self
+            .get_repo_config(repo)
+            .await?
+            .map(|config| GitRepo::from(repo, config)))
+    }
+
+    /// Create a repository object named `name` under a repository group, along with its config
+    /// entry. If `maybe_repo_uuid` is None, a new UUID is allocated by the ConfigDB.
+    ///
+    /// If the config entry can't be written, the new object is deleted again.
+    pub async fn create_repo(
+        &self,
+        group: uuid::Uuid,
+        name: &str,
+        maybe_repo_uuid: Option<uuid::Uuid>,
+    ) -> Result<GitRepo, FetchError> {
+        let group_config = self
+            .get_repo_config(group)
+            .await?
+            .ok_or_else(|| FetchError {
+                message: format!("Repository group {} has no config.", group),
+                url: format!("/v1/app/{}/object/{}", uuids::app::GIT_REPO_CONFIG, group),
+                kind: FetchErrorKind::Other,
+            })?;
+
+        let config = GitRepoConfig {
+            path: format!("{}/{}", group_config.path.trim_end_matches('/'), name),
+        };
+        let repo = self
+            .config_db
+            .create_object(uuids::types::GIT_REPO, maybe_repo_uuid, true)
+            .await?;
+
+        let put_result = self
+            .config_db
+            .put_config_from(uuids::app::GIT_REPO_CONFIG, repo, &config)
+            .await;
+
+        match put_result {
+            Ok(res) if res.status.is_success() => Ok(GitRepo::from(repo, config)),
+            failure => {
+                // Don't leave an object behind without its config.
+                let _ = self.config_db.delete_object(repo).await;
+                Err(match failure {
+                    Err(e) => e,
+                    Ok(res) => FetchError {
+                        message: format!(
+                            "{}: Couldn't write config for repository {}.",
+                            res.status, repo
+                        ),
+                        url: format!("/v1/app/{}/object/{}", uuids::app::GIT_REPO_CONFIG, repo),
+                        kind: FetchErrorKind::Other,
+                    },
+                })
+            }
+        }
+    }
+
+    /// Get the HTTP clone URL of a repository, using the Git service URL from the Directory.
+    pub async fn clone_url(&self, repo: uuid::Uuid) -> Result<String, FetchError> {
+        let config = self
+            .get_repo_config(repo)
+            .await?
+            .ok_or_else(|| FetchError {
+                message: format!("Repository {} has no config.", repo),
+                url: format!("/v1/app/{}/object/{}", uuids::app::GIT_REPO_CONFIG, repo),
+                kind: FetchErrorKind::Other,
+            })?;
+        let git_url = self
+            .service_http
+            .resolve_service_url(&ServiceType::Git)
+            .await?;
+
+        Ok(format!(
+            "{}/git/{}",
+            git_url,
+            config.path.trim_start_matches('/')
+        ))
+    }
+
+    /// Get a token for the Git service from the shared token store. This can be given as a bearer
+    /// token to authenticate git over HTTP.
+    pub async fn token(&self) -> Result<TokenStruct, FetchError> {
+        self.service_http.get_token(ServiceType::Git).await
+    }
+
+    async fn list_with_config(&self, class: uuid::Uuid) -> Result<Vec<GitRepo>, FetchError> {
+        let mut repos = Vec::new();
+        for repo in self.config_db.list_class_members(class).await? {
+            if let Some(config) = self.get_repo_config(repo).await? {
+                repos.push(GitRepo::from(repo, config));
+            }
+        }
+
+        Ok(repos)
+    }
+
+    async fn get_repo_config(&self, obj: uuid::Uuid) -> Result<Option<GitRepoConfig>, FetchError> {
+        self.config_db
+            .get_config_as(uuids::app::GIT_REPO_CONFIG, obj)
+            .await
+    }
+}
+
+pub mod git_models {
+    //! Contains structs and implementations for representations of Git service elements.
+
+    /// The config entry of a repository or repository group.
+    #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+    pub struct GitRepoConfig {
+        pub path: String,
+    }
+
+    /// A repository or repository group with its config entry.
+    #[derive(Debug, Clone)]
+    pub struct GitRepo {
+        pub uuid: uuid::Uuid,
+        pub path: String,
+    }
+
+    impl GitRepo {
+        pub fn from(uuid: uuid::Uuid, config: GitRepoConfig) -> Self {
+            GitRepo {
+                uuid,
+                path: config.path,
+            }
+        }
+    }
+}
@@ -1,7 +1,6 @@
 //! This module provides an implementation of MQTTInterface for interacting with the Factory+
 //! MQTT service.
 
-use std::collections::HashMap;
 use std::str::FromStr;
 use std::sync::mpsc;
 use std::sync::Arc;
@@ -10,40 +9,22 @@ use std::time::Duration;
 use paho_mqtt::ReasonCode;
 use sparkplug_rs;
 use sparkplug_rs::protobuf::Message as ProtobufMessage;
-use tokio::sync::Mutex;
 
 use crate::error::MqttError;
 use crate::service::mqtt::protocol::MqttProtocol;
-use crate::service::response::TokenStruct;
+use crate::service::service_http::ServiceHttp;
 use crate::service::ServiceType;
 use crate::sparkplug::util::topic::Topic;
 
 /// The interface for the Factory+ MQTT service.
 pub struct MQTTInterface {
-    service_type: ServiceType,
-    service_username: String,
-    service_password: String,
-    http_client: Arc<reqwest::Client>,
-    pub service_url: String,
-    tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
+    service_http: Arc<ServiceHttp>,
 }
 
 impl MQTTInterface {
-    pub fn from(
-        service_username: String,
-        service_password: String,
-        http_client: Arc<reqwest::Client>,
-        service_url: String,
-        tokens: Arc<Mutex<HashMap<ServiceType, TokenStruct>>>,
-    ) -> Self {
-        MQTTInterface {
-            service_type: ServiceType::MQTT,
-            service_username,
-            service_password,
-            http_client,
-            service_url,
-            tokens,
-        }
+    /// Create a new `MQTTInterface` which makes its requests through the given service HTTP layer.
+    pub fn from(service_http: Arc<ServiceHttp>) -> Self {
+        MQTTInterface { service_http }
     }
 
     /// Attempt to obtain a paho_mqtt::AsyncClient connected to the host at the uri specified by the
@@ -62,12 +43,17 @@ impl MQTTInterface {
         ),
         MqttError,
     > {
+        let service_url = self
+            .service_http
+            .resolve_service_url(&ServiceType::MQTT)
+            .await?;
+
         match self
             .basic_async_client(
-                format!("{}:{}", &self.service_url, port),
+                format!("{}:{}", service_url, port),
                 client_id,
-                self.service_username.clone(),
-                self.service_password.clone(),
+                self.service_http.service_creds().service_username.clone(),
+                self.service_http.service_creds().service_password.clone(),
             )
             .await
         {
@@ -0,0 +1,488 @@
+//! This module provides ServiceHttp, the authenticated HTTP layer shared by the service interfaces.
+//!
+//! ServiceHttp owns the HTTP client, the service credentials, the token store, and the known
+//! service urls. Every interface makes its requests through it.
+//!
+//! Requests are made with a url relative to the service (e.g. `/v1/object`), which is resolved
+//! against the service's url. Service urls are looked up through a DiscoveryInterface: urls which
+//! haven't been configured are found in the Directory and cached.
+//!
+//! When a service has several providers, they are tried in the order given by the service's
+//! `ProviderSelection`. A request which couldn't connect to a provider is retried on the next one.
+//! Idempotent requests are also retried on the next provider if they fail in any other way or the
+//! provider reports itself unavailable.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::error::{FetchError, FetchErrorKind};
+use crate::service::directory::service_provider;
+use crate::service::discovery::{DiscoveryInterface, ProviderSelection};
+use crate::service::request::{FetchOpts, HttpRequestMethod};
+use crate::service::response::{FetchResponse, TokenStruct};
+use crate::service::token::TokenStore;
+use crate::service::{fetch_util, utils, ServiceCreds, ServiceType};
+
+/// The authenticated HTTP layer for the Factory+ services.
+pub struct ServiceHttp {
+    http_client: Arc<reqwest::Client>,
+    tokens: Arc<TokenStore>,
+    service_creds: ServiceCreds,
+    discovery: RwLock<DiscoveryInterface>,
+}
+
+impl ServiceHttp {
+    /// Create a new `ServiceHttp` from a username, password, and HTTP client.
+    pub fn from(
+        service_username: &str,
+        service_password: &str,
+        http_client: Arc<reqwest::Client>,
+    ) -> Self {
+        ServiceHttp {
+            http_client,
+            tokens: Arc::new(TokenStore::new()),
+            service_creds: ServiceCreds::from(service_username, service_password),
+            discovery: RwLock::new(DiscoveryInterface::from(None, None, None, None, None)),
+        }
+    }
+
+    /// Set the url of a service. Requests to the service will use this url rather than one found
+    /// in the Directory.
+    pub fn set_service_url(&self, service: ServiceType, service_url: String) {
+        if let Ok(mut discovery) = self.discovery.write() {
+            discovery.set_service_url(service, service_url);
+        }
+    }
+
+    /// Add a url for a service alongside any already configured, bypassing the Directory.
+    pub fn add_service_url(&self, service: ServiceType, service_url: String) {
+        if let Ok(mut discovery) = self.discovery.write() {
+            discovery.add_service_url(service, service_url);
+        }
+    }
+
+    /// Set how long service urls found in the Directory are cached for.
+    pub fn set_discovery_ttl(&self, ttl: Duration) {
+        if let Ok(mut discovery) = self.discovery.write() {
+            discovery.set_ttl(ttl);
+        }
+    }
+
+    /// Set how to choose between the providers of a service.
+    pub fn set_provider_selection(&self, service: ServiceType, selection: ProviderSelection) {
+        if let Ok(mut discovery) = self.discovery.write() {
+            discovery.set_provider_selection(service, selection);
+        }
+    }
+
+    /// Get the url of a service if it is configured or cached.
+    pub fn service_url(&self, service: &ServiceType) -> Option<String> {
+        self.known_service_urls(service)
+            .and_then(|urls| urls.into_iter().next())
+    }
+
+    /// Stop using a url for a service until the Directory advertises it again.
+    pub fn invalidate_service_url(&self, service: &ServiceType, service_url: &str) {
+        if let Ok(mut discovery) = self.discovery.write() {
+            discovery.invalidate_service_url(service, service_url);
+        }
+    }
+
+    /// Get the url of a service, looking it up in the Directory if it isn't configured or cached.
+    pub async fn resolve_service_url(&self, service: &ServiceType) -> Result<String, FetchError> {
+        let service_urls = self.resolve_service_urls(service).await?;
+        service_urls.into_iter().next().ok_or_else(|| FetchError {
+            message: format!("No known url for the {}.", service),
+            url: String::new(),
+            kind: FetchErrorKind::Other,
+        })
+    }
+
+    /// Get every known url of a service, looking them up in the Directory if none are configured
+    /// or cached.
+    pub async fn resolve_service_urls(
+        &self,
+        service: &ServiceType,
+    ) -> Result<Vec<String>, FetchError> {
+        if let Some(service_urls) = self.known_service_urls(service) {
+            return Ok(service_urls);
+        }
+
+        let directory_url = match (service, self.service_url(&ServiceType::Directory)) {
+            (ServiceType::Directory, _) | (_, None) => {
+                return Err(FetchError {
+                    message: format!("No known url for the {}.", service),
+                    url: String::new(),
+                    kind: FetchErrorKind::Other,
+                })
+            }
+            (_, Some(directory_url)) => directory_url,
+        };
+
+        let lookup_url = format!("{}/v1/service/{}", directory_url, service.to_uuid());
+        let fetch_opts = FetchOpts {
+            url: lookup_url.clone(),
+            service: ServiceType::Directory,
+            method: HttpRequestMethod::GET,
+            headers: Default::default(),
+            query: None,
+            body: None,
+        };
+
+        let response = self
+            .send_authenticated(&fetch_opts, directory_url, lookup_url.clone())
+            .await?;
+
+        let service_urls = match response.status {
+            http::StatusCode::OK => service_provider::urls_from_response(&response.content)
+                .map_err(|_| FetchError {
+                    message: String::from("Couldn't decode service response."),
+                    url: lookup_url.clone(),
+                    kind: FetchErrorKind::Other,
+                })?,
+            _ => Vec::new(),
+        };
+
+        if service_urls.is_empty() {
+            Err(FetchError {
+                message: format!(
+                    "{}: No advertised url for the {}.",
+                    response.status, service
+                ),
+                url: lookup_url,
+                kind: FetchErrorKind::Other,
+            })
+        } else {
+            if let Ok(mut discovery) = self.discovery.write() {
+                discovery.cache_service_urls(service.clone(), service_urls.clone());
+            }
+            Ok(service_urls)
+        }
+    }
+
+    fn known_service_urls(&self, service: &ServiceType) -> Option<Vec<String>> {
+        self.discovery
+            .read()
+            .ok()
+            .and_then(|discovery| discovery.known_service_urls(service))
+    }
+
+    pub fn http_client(&self) -> Arc<reqwest::Client> {
+        Arc::clone(&self.http_client)
+    }
+
+    pub fn tokens(&self) -> Arc<TokenStore> {
+        Arc::clone(&self.tokens)
+    }
+
+    pub(crate) fn service_creds(&self) -> &ServiceCreds {
+        &self.service_creds
+    }
+
+    /// Get a valid token for the service, requesting a new one if needed.
+    pub async fn get_token(&self, service: ServiceType) -> Result<TokenStruct, FetchError> {
+        let service_url = self.resolve_service_url(&service).await?;
+        self.token_for(service, service_url, false).await
+    }
+
+    /// Request a new token for the service regardless of any held token.
+    pub async fn refresh_token(&self, service: ServiceType) -> Result<TokenStruct, FetchError> {
+        let service_url = self.resolve_service_url(&service).await?;
+        self.token_for(service, service_url, true).await
+    }
+
+    /// Make a request to a service authenticated with a token for `fetch_opts.service`.
+    ///
+    /// A url starting with `/` is taken to be relative to the service's url, and is tried against
+    /// each of the service's providers in turn until one succeeds or can't be failed over from.
+    /// If the token is rejected with a 401, a new token is requested and the request is retried
+    /// once.
+    pub async fn fetch(&self, fetch_opts: FetchOpts) -> Result<FetchResponse, FetchError> {
+        if !fetch_opts.url.starts_with('/') {
+            let service_url = self.resolve_service_url(&fetch_opts.service).await?;
+            return self.fetch_from(&fetch_opts, service_url).await;
+        }
+
+        let is_idempotent = fetch_util::is_idempotent(&fetch_opts);
+        let mut tried_urls: Vec<String> = Vec::new();
+        let mut last_result = None;
+        let mut service_urls: VecDeque<String> =
+            self.ordered_service_urls(&fetch_opts.service).await?.into();
+
+        loop {
+            let Some(service_url) = service_urls.pop_front() else {
+                // Failed providers are dropped from the cache, so once every provider has been
+                // tried the Directory is asked again and any newly advertised providers are tried
+                // too. If it can't be asked, the last provider's failure is returned.
+                let untried_urls: Vec<String> =
+                    match self.resolve_service_urls(&fetch_opts.service).await {
+                        Ok(urls) => urls
+                            .into_iter()
+                            .filter(|url| !tried_urls.contains(url))
+                            .collect(),
+                        Err(_) => Vec::new(),
+                    };
+                if untried_urls.is_empty() {
+                    break;
+                }
+                service_urls = self
+                    .order_providers(&fetch_opts.service, untried_urls)
+                    .into();
+                continue;
+            };
+
+            let result = self.fetch_from(&fetch_opts, service_url.clone()).await;
+
+            if !should_fail_over(&result, is_idempotent) {
+                if result.is_ok() {
+                    self.mark_healthy(&service_url);
+                }
+                return result;
+            }
+
+            self.mark_unhealthy(&service_url);
+            self.invalidate_service_url(&fetch_opts.service, &service_url);
+            tried_urls.push(service_url);
+            last_result = Some(result);
+        }
+
+        last_result.unwrap_or_else(|| {
+            Err(FetchError {
+                message: format!("No known url for the {}.", fetch_opts.service),
+                url: fetch_opts.url.clone(),
+                kind: FetchErrorKind::Other,
+            })
+        })
+    }
+
+    async fn ordered_service_urls(&self, service: &ServiceType) -> Result<Vec<String>, FetchError> {
+        let service_urls = self.resolve_service_urls(service).await?;
+        Ok(self.order_providers(service, service_urls))
+    }
+
+    fn order_providers(&self, service: &ServiceType, service_urls: Vec<String>) -> Vec<String> {
+        match self.discovery.write() {
+            Ok(mut discovery) => discovery.order_providers(service, service_urls),
+            Err(_) => service_urls,
+        }
+    }
+
+    fn mark_healthy(&self, service_url: &str) {
+        if let Ok(mut discovery) = self.discovery.write() {
+            discovery.mark_healthy(service_url);
+        }
+    }
+
+    fn mark_unhealthy(&self, service_url: &str) {
+        if let Ok(mut discovery) = self.discovery.write() {
+            discovery.mark_unhealthy(service_url);
+        }
+    }
+
+    async fn fetch_from(
+        &self,
+        fetch_opts: &FetchOpts,
+        service_url: String,
+    ) -> Result<FetchResponse, FetchError> {
+        let request_url = if fetch_opts.url.starts_with('/') {
+            format!("{}{}", service_url, fetch_opts.url)
+        } else {
+            fetch_opts.url.clone()
+        };
+
+        self.send_authenticated(fetch_opts, service_url, request_url)
+            .await
+    }
+
+    async fn token_for(
+        &self,
+        service: ServiceType,
+        service_url: String,
+        force_refresh: bool,
+    ) -> Result<TokenStruct, FetchError> {
+        let (username, password) = (
+            &self.service_creds.service_username,
+            &self.service_creds.service_password,
+        );
+        if force_refresh {
+            self.tokens
+                .refresh_token(self.http_client(), service, service_url, username, password)
+                .await
+        } else {
+            self.tokens
+                .get_token(self.http_client(), service, service_url, username, password)
+                .await
+        }
+    }
+
+    /// Send a request to `request_url` authenticated with a token from the service at
+    /// `service_url`, retrying once with a new token on a 401.
+    async fn send_authenticated(
+        &self,
+        fetch_opts: &FetchOpts,
+        service_url: String,
+        request_url: String,
+    ) -> Result<FetchResponse, FetchError> {
+        let current_token = self
+            .token_for(fetch_opts.service.clone(), service_url.clone(), false)
+            .await?;
+
+        let response = self
+            .send_request(fetch_opts, &request_url, &current_token.token)
+            .await?;
+
+        if response.status == http::StatusCode::UNAUTHORIZED {
+            let new_token = self
+                .token_for(fetch_opts.service.clone(), service_url, true)
+                .await?;
+            self.send_request(fetch_opts, &request_url, &new_token.token)
+                .await
+        } else {
+            Ok(response)
+        }
+    }
+
+    /// Build and send the request described by the fetch options with the given bearer token.
+    async fn send_request(
+        &self,
+        fetch_opts: &FetchOpts,
+        request_url: &str,
+        token: &str,
+    ) -> Result<FetchResponse, FetchError> {
+        let headers =
+            utils::check_correct_headers(&fetch_opts.headers, &fetch_opts.body, request_url)?;
+
+        let mut request_builder = self
+            .http_client
+            .request(fetch_opts.method.to_method(), request_url)
+            .headers(headers);
+        if let Some(query) = &fetch_opts.query {
+            request_builder = request_builder.query(query);
+        }
+        if let Some(body) = &fetch_opts.body {
+            request_builder = request_builder.body(body.clone());
+        }
+
+        if let Ok(request) = request_builder.bearer_auth(token).build() {
+            match self.http_client.execute(request).await {
+                Ok(response) => {
+                    let response_status = response.status();
+                    let response_etag = response
+                        .headers()
+                        .get(http::header::ETAG)
+                        .and_then(|etag| etag.to_str().ok())
+                        .map(String::from);
+
+                    if let Ok(response_body) = response.text().await {
+                        Ok(FetchResponse {
+                            status: response_status,
+                            content: response_body,
+                            etag: response_etag,
+                        })
+                    } else {
+                        Err(FetchError {
+                            message: String::from("Couldn't decode response body."),
+                            url: String::from(request_url),
+                            kind: FetchErrorKind::Other,
+                        })
+                    }
+                }
+                Err(e) if e.is_connect() => {
+                    Err(FetchError::connection_failed(String::from(request_url)))
+                }
+                Err(_) => Err(FetchError {
+                    message: String::from("Couldn't make request."),
+                    url: String::from(request_url),
+                    kind: FetchErrorKind::Other,
+                }),
+            }
+        } else {
+            Err(FetchError {
+                message: String::from("Couldn't build a request to fetch."),
+                url: String::from(request_url),
+                kind: FetchErrorKind::Other,
+            })
+        }
+    }
+}
+
+/// Whether a request which got this result from one provider should be tried on the next.
+///
+/// A request which couldn't connect is always failed over. An idempotent request is also failed
+/// over after any other error, or if the provider reports itself unavailable.
+fn should_fail_over(result: &Result<FetchResponse, FetchError>, is_idempotent: bool) -> bool {
+    match result {
+        Err(e) => is_idempotent || e.is_connection_failure(),
+        Ok(response) => is_idempotent && is_unavailable(response.status),
+    }
+}
+
+/// Whether a response status means the provider can't serve requests right now, so an idempotent
+/// request should be tried on another provider.
+fn is_unavailable(status: http::StatusCode) -> bool {
+    matches!(
+        status,
+        http::StatusCode::BAD_GATEWAY
+            | http::StatusCode::SERVICE_UNAVAILABLE
+            | http::StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: http::StatusCode) -> Result<FetchResponse, FetchError> {
+        Ok(FetchResponse::from(status, String::new()))
+    }
+
+    fn error(kind: FetchErrorKind) -> Result<FetchResponse, FetchError> {
+        Err(FetchError {
+            message: String::new(),
+            url: String::new(),
+            kind,
+        })
+    }
+
+    #[test]
+    fn connection_failures_always_fail_over() {
+        assert!(should_fail_over(
+            &error(FetchErrorKind::ConnectionFailed),
+            false
+        ));
+        assert!(should_fail_over(
+            &error(FetchErrorKind::ConnectionFailed),
+            true
+        ));
+    }
+
+    #[test]
+    fn other_errors_only_fail_over_when_idempotent() {
+        assert!(should_fail_over(&error(FetchErrorKind::Other), true));
+        assert!(!should_fail_over(&error(FetchErrorKind::Other), false));
+    }
+
+    #[test]
+    fn unavailable_responses_only_fail_over_when_idempotent() {
+        for status in [
+            http::StatusCode::BAD_GATEWAY,
+            http::StatusCode::SERVICE_UNAVAILABLE,
+            http::StatusCode::GATEWAY_TIMEOUT,
+        ] {
+            assert!(should_fail_over(&response(status), true));
+            assert!(!should_fail_over(&response(status), false));
+        }
+    }
+
+    #[test]
+    fn other_responses_are_returned() {
+        for status in [
+            http::StatusCode::OK,
+            http::StatusCode::NOT_FOUND,
+            http::StatusCode::INTERNAL_SERVER_ERROR,
+        ] {
+            assert!(!should_fail_over(&response(status), true));
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! This module provides TokenStore, the token cache shared by all the service interfaces.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::FutureExt;
+use tokio::sync::Mutex;
+
+use crate::error::FetchError;
+use crate::service::fetch_util;
+use crate::service::response::TokenStruct;
+use crate::service::{InFlightTokensMap, ServiceType};
+
+/// How long before a token's expiry it is refreshed, unless configured otherwise.
+pub const DEFAULT_REFRESH_SKEW: Duration = Duration::from_secs(60);
+
+/// The service a token is for and the url of the provider which issued it. Each provider of a
+/// service issues its own tokens, so a token from one provider isn't used with another.
+pub type TokenKey = (ServiceType, String);
+
+/// Holds a token per service provider, fetching a new one when there is none or the held token is
+/// about to expire.
+///
+/// Token requests are single-flight per service provider: concurrent callers needing a new token
+/// from the same provider share one pending request, while callers for other services or
+/// providers are not held up by it.
+pub struct TokenStore {
+    tokens: Mutex<HashMap<TokenKey, TokenStruct>>,
+    in_flight: Mutex<InFlightTokensMap>,
+    refresh_skew_millis: AtomicU64,
+}
+
+impl TokenStore {
+    /// Create an empty `TokenStore` which refreshes tokens `DEFAULT_REFRESH_SKEW` ahead of expiry.
+    pub fn new() -> Self {
+        TokenStore {
+            tokens: Mutex::new(HashMap::new()),
+            in_flight: Mutex::new(HashMap::new()),
+            refresh_skew_millis: AtomicU64::new(DEFAULT_REFRESH_SKEW.as_millis() as u64),
+        }
+    }
+
+    /// How long before expiry a held token is treated as expired.
+    pub fn refresh_skew(&self) -> Duration {
+        Duration::from_millis(self.refresh_skew_millis.load(Ordering::Relaxed))
+    }
+
+    /// Set how long before expiry a held token is treated as expired.
+    pub fn set_refresh_skew(&self, skew: Duration) {
+        self.refresh_skew_millis
+            .store(skew.as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// Get a valid token for the service, requesting a new one from the service if there is no
+    /// held token or the held token expires within the refresh skew.
+    pub async fn get_token(
+        &self,
+        client: Arc<reqwest::Client>,
+        service: ServiceType,
+        service_url: String,
+        username: &str,
+        password: &str,
+    ) -> Result<TokenStruct, FetchError> {
+        let request = new_token_request(client, service_url.clone(), username, password);
+        self.get_token_with((service, service_url), request).await
+    }
+
+    /// Request a new token from the service regardless of any held token, and hold it. If a token
+    /// request for the service is already in flight, its result is used instead.
+    pub async fn refresh_token(
+        &self,
+        client: Arc<reqwest::Client>,
+        service: ServiceType,
+        service_url: String,
+        username: &str,
+        password: &str,
+    ) -> Result<TokenStruct, FetchError> {
+        let request = new_token_request(client, service_url.clone(), username, password);
+        self.acquire_token((service, service_url), true, request)
+            .await
+    }
+
+    async fn get_token_with(
+        &self,
+        key: TokenKey,
+        request: impl FnOnce() -> TokenRequest,
+    ) -> Result<TokenStruct, FetchError> {
+        if let Some(token) = self.valid_token(&key).await {
+            return Ok(token);
+        }
+
+        self.acquire_token(key, false, request).await
+    }
+
+    async fn valid_token(&self, key: &TokenKey) -> Option<TokenStruct> {
+        self.tokens
+            .lock()
+            .await
+            .get(key)
+            .filter(|token| !token.expires_within(self.refresh_skew()))
+            .cloned()
+    }
+
+    /// Wait for a new token for `key`, joining the pending request for it if there is one and
+    /// otherwise starting `request`.
+    async fn acquire_token(
+        &self,
+        key: TokenKey,
+        force_refresh: bool,
+        request: impl FnOnce() -> TokenRequest,
+    ) -> Result<TokenStruct, FetchError> {
+        // Join the pending request for this provider if there is one, otherwise start one. The
+        // in-flight lock is only held while doing this, never across the request itself.
+        let pending = {
+            let mut in_flight = self.in_flight.lock().await;
+            if let Some(pending) = in_flight.get(&key) {
+                pending.clone()
+            } else {
+                // Another caller may have finished a request since we last checked.
+                if !force_refresh {
+                    if let Some(token) = self.valid_token(&key).await {
+                        return Ok(token);
+                    }
+                }
+
+                let pending = request().shared();
+                in_flight.insert(key.clone(), pending.clone());
+                pending
+            }
+        };
+
+        let result = pending.clone().await;
+
+        // The first caller to see the result holds the new token and clears the in-flight entry.
+        let mut in_flight = self.in_flight.lock().await;
+        if in_flight
+            .get(&key)
+            .is_some_and(|current| current.ptr_eq(&pending))
+        {
+            in_flight.remove(&key);
+            if let Ok(token) = &result {
+                self.tokens.lock().await.insert(key, token.clone());
+            }
+        }
+
+        result
+    }
+
+    /// Get a copy of the currently held tokens, by service and provider url.
+    pub async fn snapshot(&self) -> HashMap<TokenKey, TokenStruct> {
+        self.tokens.lock().await.clone()
+    }
+}
+
+/// A request for a new token, which is shared between every caller waiting on it.
+type TokenRequest = Pin<Box<dyn Future<Output = Result<TokenStruct, FetchError>> + Send>>;
+
+/// Build the request for a new token from the service at `service_url`. The request isn't sent
+/// until the returned function is called and its future awaited.
+fn new_token_request(
+    client: Arc<reqwest::Client>,
+    service_url: String,
+    username: &str,
+    password: &str,
+) -> impl FnOnce() -> TokenRequest {
+    let username = String::from(username);
+    let password = String::from(password);
+    move || {
+        Box::pin(async move {
+            fetch_util::get_new_token(client, service_url, &username, &password).await
+        })
+    }
+}
+
+impl Default for TokenStore {
+    fn default() -> Self {
+        TokenStore::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+
+    use tokio::sync::Notify;
+
+    use super::*;
+
+    fn key(service: ServiceType) -> TokenKey {
+        (service, String::from("http://provider"))
+    }
+
+    fn far_future_token(token: &str) -> TokenStruct {
+        TokenStruct::from(String::from(token), u64::MAX)
+    }
+
+    /// A token request which counts how often it is started and, if given a `Notify`, waits for
+    /// it before completing.
+    fn counted_request(
+        requests: Arc<AtomicUsize>,
+        release: Option<Arc<Notify>>,
+        token: &'static str,
+    ) -> impl FnOnce() -> TokenRequest {
+        move || {
+            Box::pin(async move {
+                requests.fetch_add(1, Ordering::SeqCst);
+                if let Some(release) = release {
+                    release.notified().await;
+                }
+                Ok(far_future_token(token))
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_requests_share_one_fetch_per_service() {
+        let store = TokenStore::new();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let release = Arc::new(Notify::new());
+
+        let first = store.get_token_with(
+            key(ServiceType::ConfigDb),
+            counted_request(Arc::clone(&requests), Some(Arc::clone(&release)), "a"),
+        );
+        let second = store.get_token_with(
+            key(ServiceType::ConfigDb),
+            counted_request(Arc::clone(&requests), Some(Arc::clone(&release)), "b"),
+        );
+        // The ConfigDB request can only finish once this one has, so this also shows that a
+        // pending request for one service doesn't hold up another.
+        let other = async {
+            let token = store
+                .get_token_with(
+                    key(ServiceType::Directory),
+                    counted_request(Arc::clone(&requests), None, "c"),
+                )
+                .await;
+            release.notify_one();
+            token
+        };
+
+        let (first, second, other) = tokio::time::timeout(Duration::from_secs(5), async {
+            tokio::join!(first, second, other)
+        })
+        .await
+        .expect("token requests didn't finish");
+
+        assert_eq!(first.unwrap().token, "a");
+        assert_eq!(second.unwrap().token, "a");
+        assert_eq!(other.unwrap().token, "c");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn held_token_is_reused_until_refreshed() {
+        let store = TokenStore::new();
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..2 {
+            let token = store
+                .get_token_with(
+                    key(ServiceType::ConfigDb),
+                    counted_request(Arc::clone(&requests), None, "a"),
+                )
+                .await;
+            assert_eq!(token.unwrap().token, "a");
+        }
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+
+        let token = store
+            .acquire_token(
+                key(ServiceType::ConfigDb),
+                true,
+                counted_request(Arc::clone(&requests), None, "b"),
+            )
+            .await;
+        assert_eq!(token.unwrap().token, "b");
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn tokens_are_held_per_provider() {
+        let store = TokenStore::new();
+        let requests = Arc::new(AtomicUsize::new(0));
+
+        for provider in ["http://a", "http://b", "http://a"] {
+            store
+                .get_token_with(
+                    (ServiceType::ConfigDb, String::from(provider)),
+                    counted_request(Arc::clone(&requests), None, "a"),
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
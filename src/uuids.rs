@@ -32,6 +32,7 @@ pub mod app {
     pub const SPARKPLUG_ADDRESS: uuid::Uuid = uuid::uuid!("8e32801b-f35a-4cbf-a5c3-2af64d3debd7");
     pub const CONFIG_SCHEMA: uuid::Uuid = uuid::uuid!("dbd8a535-52ba-4f6e-b4f8-9b71aefe09d3");
     pub const SERVICE_CONFIG: uuid::Uuid = uuid::uuid!("5b47881c-b012-4040-945c-eacafca539b2");
+    pub const GIT_REPO_CONFIG: uuid::Uuid = uuid::uuid!("38d62a93-b6b4-4f63-bad4-d433e3eaff29");
 }
 
 pub mod schema {